@@ -0,0 +1,57 @@
+//! Times the tokenizer on growing inputs. The time per byte should stay flat
+//! as the input grows; if it climbs with the size, lexing has gone quadratic.
+//!
+//! Run with `cargo bench --bench tokenizer`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use compiler_11::lexer::tokenizer::Tokenizer;
+
+const SNIPPET: &str = r#"/// Greets someone.
+func grüße(name string, times int): string {
+    var größe float = 1.5e3 + 0xFF_FF // mixed scripts: 名前, ñ
+    /* a block /* nested */ comment */
+    return "hallo {name} ✓\n" + r"raw\path"
+}
+"#;
+
+/// Lexes `source` to the end and returns how many tokens it held.
+fn tokenize_all(source: &str) -> usize {
+    Tokenizer::new(source).map(black_box).count()
+}
+
+fn time(source: &str) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(tokenize_all(black_box(source)));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// A single line with no line breaks, where every column is found far from
+/// the start of its line.
+const LONG_LINE_SNIPPET: &str = "ab + größe * ";
+
+fn report(label: &str, megabytes: usize, source: &str) {
+    let elapsed = time(source);
+    eprintln!(
+        "{:>9} {:>2} MB: {:>8.1} ms, {:>6.1} ns/byte",
+        label,
+        megabytes,
+        elapsed.as_secs_f64() * 1e3,
+        elapsed.as_nanos() as f64 / source.len() as f64,
+    );
+}
+
+fn main() {
+    for megabytes in [1, 2, 4, 8] {
+        report("lines", megabytes, &SNIPPET.repeat(megabytes * 1024 * 1024 / SNIPPET.len()));
+    }
+    for megabytes in [1, 2, 4, 8] {
+        report("one line", megabytes, &LONG_LINE_SNIPPET.repeat(megabytes * 1024 * 1024 / LONG_LINE_SNIPPET.len()));
+    }
+}
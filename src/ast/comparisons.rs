@@ -1,14 +1,24 @@
 use crate::{
-    ast::structure::StructDef, data_type::DataType, lexer::token::Token
+    ast::structure::StructDef, data_type::DataType,
 };
 
 use super::structure::{
+    Assignment,
+    Else,
     Expression,
+    FieldAccess,
     FunctionCall,
+    MethodCall,
+    For,
     FunctionDef,
+    If,
+    Interpolation,
+    InterpolationPart,
     OperatorUse,
+    UnaryOp,
     ValidInFunctionBody,
     Variable,
+    While,
 };
 
 // ---- Comparison utilities and traits ----
@@ -82,6 +92,15 @@ fn compare_vec<T: AstComparable>(
     if errors.is_empty() { Ok(()) } else { Err(errors) }
 }
 
+fn compare_doc(a: &Option<String>, b: &Option<String>, errors: &mut Vec<ComparisonError>) {
+    if a != b {
+        errors.push(ComparisonError::MismatchedValues(format!(
+            "Doc comment mismatch: {:?} != {:?}",
+            a, b
+        )));
+    }
+}
+
 // ---- Primitive/leaf impls ----
 impl AstComparable for String {
     fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
@@ -106,6 +125,8 @@ impl AstComparable for DataType {
 }
 
 // ---- AST node comparison impls ----
+// Spans are deliberately left out: two trees are equal if they have the same
+// shape, wherever in the source they came from.
 impl<'a> AstComparable for FunctionCall<'a> {
     fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
         let mut errors = Vec::new();
@@ -124,6 +145,45 @@ impl<'a> AstComparable for FunctionCall<'a> {
 
 impl<'a> PartialEq for FunctionCall<'a> { fn eq(&self, other: &Self) -> bool { self.compare(other).is_ok() } }
 
+impl<'a> AstComparable for FieldAccess<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        let mut errors = Vec::new();
+        if self.field != other.field {
+            errors.push(ComparisonError::MismatchedValues(format!(
+                "Field name mismatch: '{}' != '{}'",
+                self.field, other.field
+            )));
+        }
+        if let Err(errs) = self.object.compare(&other.object) {
+            errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In field access object: {}", e))));
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+impl<'a> PartialEq for FieldAccess<'a> { fn eq(&self, other: &Self) -> bool { self.compare(other).is_ok() } }
+
+impl<'a> AstComparable for MethodCall<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        let mut errors = Vec::new();
+        if self.method != other.method {
+            errors.push(ComparisonError::MismatchedValues(format!(
+                "Method name mismatch: '{}' != '{}'",
+                self.method, other.method
+            )));
+        }
+        if let Err(errs) = self.object.compare(&other.object) {
+            errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In method call object: {}", e))));
+        }
+        if let Err(errs) = compare_vec(&self.args, &other.args, "method arguments") {
+            errors.extend(errs);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+impl<'a> PartialEq for MethodCall<'a> { fn eq(&self, other: &Self) -> bool { self.compare(other).is_ok() } }
+
 impl<'a> AstComparable for Variable<'a> {
     fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
         let mut errors = Vec::new();
@@ -142,6 +202,7 @@ impl<'a> AstComparable for Variable<'a> {
         if let Err(errs) = compare_optional(&self.value, &other.value, "variable value") {
             errors.extend(errs);
         }
+        compare_doc(&self.doc, &other.doc, &mut errors);
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 }
@@ -169,10 +230,46 @@ impl<'a> AstComparable for OperatorUse<'a> {
 
 impl<'a> PartialEq for OperatorUse<'a> { fn eq(&self, other: &Self) -> bool { self.compare(other).is_ok() } }
 
+impl<'a> AstComparable for UnaryOp<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        let mut errors = Vec::new();
+        if self.operator != other.operator {
+            errors.push(ComparisonError::MismatchedValues(format!(
+                "Operator mismatch: '{}' != '{}'",
+                self.operator, other.operator
+            )));
+        }
+        if let Err(errs) = self.operand.compare(&other.operand) {
+            errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In operand: {}", e))));
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+impl<'a> PartialEq for UnaryOp<'a> { fn eq(&self, other: &Self) -> bool { self.compare(other).is_ok() } }
+
+impl<'a> AstComparable for Interpolation<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        compare_vec(&self.parts, &other.parts, "interpolation parts")
+    }
+}
+
+impl<'a> AstComparable for InterpolationPart<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        match (self, other) {
+            (InterpolationPart::Text(a), InterpolationPart::Text(b)) => a.compare(b),
+            (InterpolationPart::Expression(a), InterpolationPart::Expression(b)) => a.compare(b),
+            _ => Err(vec![ComparisonError::MismatchedVariant]),
+        }
+    }
+}
+
 impl<'a> AstComparable for Expression<'a> {
     fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
         match (self, other) {
             (Expression::OperatorUse(a), Expression::OperatorUse(b)) => a.compare(b),
+            (Expression::UnaryOp(a), Expression::UnaryOp(b)) => a.compare(b),
+            (Expression::Interpolation(a), Expression::Interpolation(b)) => a.compare(b),
             (Expression::Token(a), Expression::Token(b)) => {
                 if a.type_ != b.type_ || a.value != b.value {
                     Err(vec![ComparisonError::MismatchedValues(format!(
@@ -181,9 +278,22 @@ impl<'a> AstComparable for Expression<'a> {
                 } else { Ok(()) }
             }
             (Expression::FunctionCall(a), Expression::FunctionCall(b)) => a.compare(b),
+            (Expression::FieldAccess(a), Expression::FieldAccess(b)) => a.compare(b),
+            (Expression::MethodCall(a), Expression::MethodCall(b)) => a.compare(b),
             (Expression::VarReference(a), Expression::VarReference(b)) => {
                 if a.name == b.name { Ok(()) } else { Err(vec![ComparisonError::MismatchedValues(format!("Var reference mismatch: '{}' != '{}'", a.name, b.name))]) }
             }
+            (Expression::Array(a), Expression::Array(b)) => compare_vec(&a.elements, &b.elements, "array elements"),
+            (Expression::Subscript(a), Expression::Subscript(b)) => {
+                let mut errors = Vec::new();
+                if let Err(errs) = a.object.compare(&b.object) {
+                    errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In subscripted object: {}", e))));
+                }
+                if let Err(errs) = a.arg.compare(&b.arg) {
+                    errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In subscript index: {}", e))));
+                }
+                if errors.is_empty() { Ok(()) } else { Err(errors) }
+            }
             _ => Err(vec![ComparisonError::MismatchedVariant]),
         }
     }
@@ -197,11 +307,97 @@ impl<'a> AstComparable for ValidInFunctionBody<'a> {
             (ValidInFunctionBody::Variable(a), ValidInFunctionBody::Variable(b)) => a.compare(b),
             (ValidInFunctionBody::Expression(a), ValidInFunctionBody::Expression(b)) => a.compare(b),
             (ValidInFunctionBody::Return(a), ValidInFunctionBody::Return(b)) => a.compare(b),
+            (ValidInFunctionBody::Assignment(a), ValidInFunctionBody::Assignment(b)) => a.compare(b),
+            (ValidInFunctionBody::If(a), ValidInFunctionBody::If(b)) => a.compare(b),
+            (ValidInFunctionBody::While(a), ValidInFunctionBody::While(b)) => a.compare(b),
+            (ValidInFunctionBody::For(a), ValidInFunctionBody::For(b)) => a.compare(b),
+            (ValidInFunctionBody::Break(_), ValidInFunctionBody::Break(_)) => Ok(()),
+            (ValidInFunctionBody::Continue(_), ValidInFunctionBody::Continue(_)) => Ok(()),
+            (ValidInFunctionBody::Error(_), ValidInFunctionBody::Error(_)) => Ok(()),
             _ => Err(vec![ComparisonError::MismatchedVariant]),
         }
     }
 }
 
+impl<'a> AstComparable for Assignment<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        let mut errors = Vec::new();
+        if self.operator != other.operator {
+            errors.push(ComparisonError::MismatchedValues(format!(
+                "Assignment operator mismatch: expected '{}', got '{}'",
+                self.operator, other.operator
+            )));
+        }
+        if let Err(errs) = self.target.compare(&other.target) {
+            errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In assignment target: {}", e))));
+        }
+        if let Err(errs) = self.value.compare(&other.value) {
+            errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In assigned value: {}", e))));
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+impl<'a> AstComparable for If<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        let mut errors = Vec::new();
+        if let Err(errs) = self.condition.compare(&other.condition) {
+            errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In if condition: {}", e))));
+        }
+        if let Err(errs) = compare_vec(&self.then_body, &other.then_body, "if body") {
+            errors.extend(errs);
+        }
+        if let Err(errs) = compare_optional(&self.else_branch, &other.else_branch, "else branch") {
+            errors.extend(errs);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+impl<'a> PartialEq for If<'a> { fn eq(&self, other: &Self) -> bool { self.compare(other).is_ok() } }
+
+impl<'a> AstComparable for Else<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        match (self, other) {
+            (Else::If(a), Else::If(b)) => a.compare(b),
+            (Else::Block(a), Else::Block(b)) => compare_vec(a, b, "else body"),
+            _ => Err(vec![ComparisonError::MismatchedVariant]),
+        }
+    }
+}
+
+impl<'a> AstComparable for While<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        let mut errors = Vec::new();
+        if let Err(errs) = self.condition.compare(&other.condition) {
+            errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In while condition: {}", e))));
+        }
+        if let Err(errs) = compare_vec(&self.body, &other.body, "while body") {
+            errors.extend(errs);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+impl<'a> AstComparable for For<'a> {
+    fn compare(&self, other: &Self) -> Result<(), Vec<ComparisonError>> {
+        let mut errors = Vec::new();
+        if self.variable != other.variable {
+            errors.push(ComparisonError::MismatchedValues(format!(
+                "For loop variable mismatch: expected '{}', got '{}'",
+                self.variable, other.variable
+            )));
+        }
+        if let Err(errs) = self.iterable.compare(&other.iterable) {
+            errors.extend(errs.into_iter().map(|e| ComparisonError::Custom(format!("In for iterable: {}", e))));
+        }
+        if let Err(errs) = compare_vec(&self.body, &other.body, "for body") {
+            errors.extend(errs);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
 impl<'a> PartialEq for ValidInFunctionBody<'a> { fn eq(&self, other: &Self) -> bool { self.compare(other).is_ok() } }
 
 impl<'a> AstComparable for FunctionDef<'a> {
@@ -225,6 +421,13 @@ impl<'a> AstComparable for FunctionDef<'a> {
         if let Err(errs) = compare_vec(&self.body, &other.body, "function body") {
             errors.extend(errs);
         }
+        if self.infix != other.infix {
+            errors.push(ComparisonError::MismatchedValues(format!(
+                "Infix mismatch: {:?} != {:?}",
+                self.infix, other.infix
+            )));
+        }
+        compare_doc(&self.doc, &other.doc, &mut errors);
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 }
@@ -245,6 +448,7 @@ impl<'a> AstComparable for StructDef<'a> {
         if let Err(errs) = compare_vec(&self.fields, &other.fields, "struct fields") {
             errors.extend(errs);
         }
+        compare_doc(&self.doc, &other.doc, &mut errors);
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 }
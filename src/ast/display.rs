@@ -2,7 +2,7 @@ use crate::{ast::StructDef, lexer::token::TokenType};
 use colored::*;
 use std::fmt;
 
-use super::structure::{Expression, FunctionCall, FunctionDef, OperatorUse, ValidInFunctionBody, Variable};
+use super::structure::{Assignment, Associativity, Else, Expression, FieldAccess, For, FunctionCall, FunctionDef, If, Interpolation, InterpolationPart, MethodCall, OperatorUse, UnaryOp, ValidInFunctionBody, Variable, While};
 
 fn format_type(s: &str) -> String { s.custom_color((80, 205, 150)).to_string() }
 fn format_keyword(s: &str) -> String { s.custom_color((20, 0, 205)).bold().to_string() }
@@ -11,8 +11,23 @@ fn format_number(s: &str) -> String { s.white().to_string() }
 fn format_operator(s: &str) -> String { s.white().to_string() }
 fn format_string(s: &str) -> String { s.custom_color((255, 195, 50)).to_string() }
 
-fn indent(f: &mut std::fmt::Formatter, depth: usize) -> fmt::Result {
-    write!(f, "{:indent$}", "", indent = depth * 2)
+/// Writes each line of a `///` comment, if there is one.
+fn write_doc(f: &mut fmt::Formatter<'_>, doc: &Option<String>) -> fmt::Result {
+    for line in doc.iter().flat_map(|doc| doc.lines()) {
+        writeln!(f, "{}", format!("/// {}", line).bright_black())?;
+    }
+    Ok(())
+}
+
+/// Writes `{`, each statement indented one level (nested blocks included), then `}`.
+fn write_block(f: &mut fmt::Formatter<'_>, body: &[ValidInFunctionBody]) -> fmt::Result {
+    writeln!(f, "{{")?;
+    for stmt in body {
+        for line in stmt.to_string().lines() {
+            writeln!(f, "    {}", line)?;
+        }
+    }
+    write!(f, "}}")
 }
 
 impl<'a> fmt::Display for FunctionCall<'a> {
@@ -26,6 +41,23 @@ impl<'a> fmt::Display for FunctionCall<'a> {
     }
 }
 
+impl<'a> fmt::Display for FieldAccess<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.object, ".".white(), format_identifier(&self.field))
+    }
+}
+
+impl<'a> fmt::Display for MethodCall<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}(", self.object, ".".white(), format_identifier(&self.method))?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 { write!(f, "{}", ", ".white())?; }
+            write!(f, "{}", arg)?;
+        }
+        write!(f, "{}", ")".white())
+    }
+}
+
 impl<'a> fmt::Display for Variable<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(expr) = &self.value {
@@ -47,21 +79,44 @@ impl<'a> fmt::Display for OperatorUse<'a> {
     }
 }
 
+impl<'a> fmt::Display for UnaryOp<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}{})", format_operator(&self.operator), self.operand)
+    }
+}
+
+impl<'a> fmt::Display for Interpolation<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_string("\""))?;
+        for part in &self.parts {
+            match part {
+                InterpolationPart::Text(text) => write!(f, "{}", format_string(&text.replace('{', "{{").replace('}', "}}")))?,
+                InterpolationPart::Expression(expression) => write!(f, "{{{}}}", expression)?,
+            }
+        }
+        write!(f, "{}", format_string("\""))
+    }
+}
+
 impl<'a> fmt::Display for Expression<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expression::OperatorUse(op) => write!(f, "{}", op),
+            Expression::UnaryOp(op) => write!(f, "{}", op),
+            Expression::Interpolation(interpolation) => write!(f, "{}", interpolation),
             Expression::Token(t) => match t.type_ {
-                TokenType::Number => write!(f, "{}", format_number(&t.value)),
+                TokenType::Number | TokenType::Float => write!(f, "{}", format_number(&t.value)),
                 TokenType::String => write!(f, "\"{}\"", format_string(&t.value)),
-                TokenType::Keyword => write!(f, "{}", format_keyword(&t.value)),
+                TokenType::Keyword | TokenType::Bool => write!(f, "{}", format_keyword(&t.value)),
                 _ => write!(f, "{}", format_identifier(&t.value)),
             },
             Expression::FunctionCall(func) => write!(f, "{}", func),
             Expression::VarReference(var_ref) => write!(f, "{}", format_identifier(&var_ref.name)),
-            Expression::Array(_) => write!(f, "{}", "array"),
-            Expression::Object(_) => write!(f, "{}", "object"),
-            Expression::Subscript(_) => write!(f, "{}", "subscript"),
+            Expression::Array(_) => write!(f, "array"),
+            Expression::Object(_) => write!(f, "object"),
+            Expression::Subscript(_) => write!(f, "subscript"),
+            Expression::FieldAccess(access) => write!(f, "{}", access),
+            Expression::MethodCall(call) => write!(f, "{}", call),
         }
     }
 }
@@ -72,12 +127,27 @@ impl<'a> fmt::Display for ValidInFunctionBody<'a> {
             ValidInFunctionBody::Variable(var) => write!(f, "{}", var),
             ValidInFunctionBody::Expression(expr) => write!(f, "{}", expr),
             ValidInFunctionBody::Return(expr) => write!(f, "{} {}", format_keyword("return"), expr),
+            ValidInFunctionBody::Assignment(assignment) => write!(f, "{}", assignment),
+            ValidInFunctionBody::If(if_) => write!(f, "{}", if_),
+            ValidInFunctionBody::While(while_) => write!(f, "{}", while_),
+            ValidInFunctionBody::For(for_) => write!(f, "{}", for_),
+            ValidInFunctionBody::Break(_) => write!(f, "{}", format_keyword("break")),
+            ValidInFunctionBody::Continue(_) => write!(f, "{}", format_keyword("continue")),
+            ValidInFunctionBody::Error(_) => write!(f, "{}", "<error>".red()),
         }
     }
 }
 
 impl<'a> fmt::Display for FunctionDef<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_doc(f, &self.doc)?;
+        if let Some(fixity) = self.infix {
+            let associativity = match fixity.associativity {
+                Associativity::Left => "left",
+                Associativity::Right => "right",
+            };
+            write!(f, "{}({}, {}) ", format_keyword("infix"), format_number(&fixity.precedence.to_string()), associativity)?;
+        }
         // Function signature
         write!(
             f,
@@ -92,16 +162,46 @@ impl<'a> fmt::Display for FunctionDef<'a> {
             write!(f, "{}: {}", format_identifier(&arg.name), format_type(&arg.type_.to_string()))?;
         }
 
-        // Return type and opening brace
-        write!(f, ") {} {} {{\n", "->".white(), format_type(&self.return_type.to_string()))?;
+        // Return type
+        write!(f, ") {} {} ", "->".white(), format_type(&self.return_type.to_string()))?;
 
         // Function body with proper indentation
-        for stmt in &self.body {
-            write!(f, "    {}\n", stmt)?;
+        write_block(f, &self.body)
+    }
+}
+
+impl<'a> fmt::Display for Assignment<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.target, format_operator(&self.operator), self.value)
+    }
+}
+
+impl<'a> fmt::Display for If<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ", format_keyword("if"), self.condition)?;
+        write_block(f, &self.then_body)?;
+        match &self.else_branch {
+            Some(Else::If(else_if)) => write!(f, " {} {}", format_keyword("else"), else_if),
+            Some(Else::Block(body)) => {
+                write!(f, " {} ", format_keyword("else"))?;
+                write_block(f, body)
+            }
+            None => Ok(()),
         }
+    }
+}
 
-        // Closing brace
-        write!(f, "}}")
+impl<'a> fmt::Display for While<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ", format_keyword("while"), self.condition)?;
+        write_block(f, &self.body)
+    }
+}
+
+impl<'a> fmt::Display for For<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {} ", format_keyword("for"), format_identifier(&self.variable), format_keyword("in"), self.iterable)?;
+        write_block(f, &self.body)
     }
 }
 
@@ -110,10 +210,14 @@ impl<'a> fmt::Display for FunctionDef<'a> {
 
 impl<'a> fmt::Display for StructDef<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {} {{\n", format_keyword("struct"), format_identifier(&self.name))?;
+        write_doc(f, &self.doc)?;
+        writeln!(f, "{} {} {{", format_keyword("struct"), format_identifier(&self.name))?;
 
         for field in &self.fields {
-            write!(f, "    {}\n", field)?;
+            for line in field.doc.iter().flat_map(|doc| doc.lines()) {
+                writeln!(f, "    {}", format!("/// {}", line).bright_black())?;
+            }
+            writeln!(f, "    {}", field)?;
         }
 
         write!(f, "}}")
@@ -5,10 +5,22 @@ pub mod comparisons;
 pub use structure::{
     Expression,
     FunctionCall,
+    FieldAccess,
+    MethodCall,
     FunctionDef,
     OperatorUse,
+    UnaryOp,
     ValidInFunctionBody,
     Variable,
     StructDef,
+    If,
+    Else,
+    While,
+    For,
+    Assignment,
+    Interpolation,
+    InterpolationPart,
+    Fixity,
+    Associativity,
 };
 pub use comparisons::{AstComparable, ComparisonError};
@@ -1,8 +1,7 @@
-use std::option;
-
 use crate::{
     data_type::DataType,
-    lexer::token::{Token, TokenType},
+    lexer::token::Token,
+    span::Span,
 };
 
 // ---- AST node types ----
@@ -10,6 +9,7 @@ use crate::{
 pub struct FunctionCall<'a> {
     pub name: String,
     pub args: Vec<Expression<'a>>,
+    pub span: Span,
 }
 
 
@@ -19,11 +19,34 @@ pub struct OperatorUse<'a> {
     pub left: Box<Expression<'a>>,
     pub operator: String,
     pub right: Box<Expression<'a>>,
+    pub span: Span,
+}
+
+/// A prefix operator applied to one operand, such as `-x` or `!done`.
+#[derive(Debug)]
+pub struct UnaryOp<'a> {
+    pub operator: String,
+    pub operand: Box<Expression<'a>>,
+    pub span: Span,
+}
+
+/// A string with expressions spliced into it, such as `"hello {name}"`.
+#[derive(Debug)]
+pub struct Interpolation<'a> {
+    pub parts: Vec<InterpolationPart<'a>>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum InterpolationPart<'a> {
+    Text(String),
+    Expression(Expression<'a>),
 }
 
 #[derive(Debug)]
 pub struct Array<'a> {
     pub elements: Vec<Expression<'a>>,
+    pub span: Span,
 }
 
 
@@ -35,40 +58,134 @@ pub struct Variable<'a> {
     pub name: String,
     pub type_: DataType,
     pub value: Option<Expression<'a>>,
+    pub span: Span,
+    /// The `///` comment written above a global or a struct field.
+    pub doc: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct VarReference<'a> {
     pub name: String,
     pub referring_to: Option<&'a Variable<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum Expression<'a> {
     OperatorUse(OperatorUse<'a>),
+    UnaryOp(UnaryOp<'a>),
     Token(Token),
+    Interpolation(Interpolation<'a>),
     FunctionCall(FunctionCall<'a>),
     VarReference(VarReference<'a>),
     Array(Array<'a>),
     Object(Object<'a>),
     Subscript(Subscript<'a>),
+    FieldAccess(FieldAccess<'a>),
+    MethodCall(MethodCall<'a>),
 }
 
 
 
+impl<'a> Expression<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::OperatorUse(op) => op.span,
+            Expression::UnaryOp(op) => op.span,
+            Expression::Token(token) => token.span,
+            Expression::Interpolation(interpolation) => interpolation.span,
+            Expression::FunctionCall(call) => call.span,
+            Expression::VarReference(reference) => reference.span,
+            Expression::Array(array) => array.span,
+            Expression::Object(object) => object.span,
+            Expression::Subscript(subscript) => subscript.span,
+            Expression::FieldAccess(access) => access.span,
+            Expression::MethodCall(call) => call.span,
+        }
+    }
+}
+
+/// `object[arg]`, reading an element of an array.
 #[derive(Debug)]
 pub struct Subscript<'a> {
-    pub name: String,
+    pub object: Box<Expression<'a>>,
     pub arg: Box<Expression<'a>>,
+    pub span: Span,
 }
 
 
 
+/// `object.field`, reading a field of a struct value.
+#[derive(Debug)]
+pub struct FieldAccess<'a> {
+    pub object: Box<Expression<'a>>,
+    pub field: String,
+    pub span: Span,
+}
+
+/// `object.method(args)`, calling a method of a struct value.
+#[derive(Debug)]
+pub struct MethodCall<'a> {
+    pub object: Box<Expression<'a>>,
+    pub method: String,
+    pub args: Vec<Expression<'a>>,
+    pub span: Span,
+}
+
 #[derive(Debug)]
 pub enum ValidInFunctionBody<'a> {
     Variable(Variable<'a>),
     Expression(Expression<'a>),
     Return(Expression<'a>),
+    Assignment(Assignment<'a>),
+    If(If<'a>),
+    While(While<'a>),
+    For(For<'a>),
+    Break(Span),
+    Continue(Span),
+    /// A statement that failed to parse; the error has already been reported.
+    Error(Span),
+}
+
+/// `target = value`, or a compound form such as `target += value`.
+#[derive(Debug)]
+pub struct Assignment<'a> {
+    pub target: Expression<'a>,
+    pub operator: String,
+    pub value: Expression<'a>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct If<'a> {
+    pub condition: Expression<'a>,
+    pub then_body: Vec<ValidInFunctionBody<'a>>,
+    pub else_branch: Option<Else<'a>>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum Else<'a> {
+    If(Box<If<'a>>),
+    Block(Vec<ValidInFunctionBody<'a>>),
+}
+
+#[derive(Debug)]
+pub struct While<'a> {
+    pub condition: Expression<'a>,
+    pub body: Vec<ValidInFunctionBody<'a>>,
+    pub span: Span,
+}
+
+/// `for variable in iterable { body }`. The variable's type comes from the
+/// elements of `iterable` and is worked out by the type checker.
+#[derive(Debug)]
+pub struct For<'a> {
+    pub variable: String,
+    pub variable_span: Span,
+    pub iterable: Expression<'a>,
+    pub body: Vec<ValidInFunctionBody<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -77,6 +194,26 @@ pub struct FunctionDef<'a> {
     pub args: Vec<Variable<'a>>,
     pub return_type: DataType,
     pub body: Vec<ValidInFunctionBody<'a>>,
+    pub span: Span,
+    /// The `///` comment written above the function.
+    pub doc: Option<String>,
+    /// Set for an `infix func`, which may also be called as `a name b`.
+    pub infix: Option<Fixity>,
+}
+
+/// How tightly an infix function holds the expressions on either side of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixity {
+    /// Higher binds tighter, on the same scale as the built-in operators.
+    pub precedence: u32,
+    pub associativity: Associativity,
+}
+
+/// Which way a chain of the same operator groups: `a - b - c` is `(a - b) - c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
 }
 
 
@@ -85,12 +222,16 @@ pub struct StructDef<'a> {
     pub name: String,
     pub fields: Vec<Variable<'a>>,
     pub methods: Vec<FunctionDef<'a>>,
+    pub span: Span,
+    /// The `///` comment written above the struct.
+    pub doc: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Object<'a> {
     pub name: String,
     pub fields: Vec<Variable<'a>>,
+    pub span: Span,
 }
 
 
@@ -99,6 +240,7 @@ pub struct Object<'a> {
 pub enum StructScopeItem<'a> {
     Field(Variable<'a>),
     Method(FunctionDef<'a>),
+    Error(Span),
 }
 
 
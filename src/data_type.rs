@@ -3,22 +3,50 @@ use std::fmt;
 #[derive(Debug, PartialEq, Clone)]
 pub enum DataType {
     Int,
+    Float,
     String,
     None,
     Bool,
-    Array,
+    /// An array of the given element type. A bare `array` has elements of
+    /// type `Unknown`.
+    Array(Box<DataType>),
     Object,
+    /// A struct declared in the source, by name.
+    Named(String),
+    /// The type of an expression that already failed to check. It is
+    /// compatible with everything so one mistake is only reported once.
+    Unknown,
+}
+
+impl DataType {
+    /// Whether a value of type `other` can be used where `self` is expected.
+    pub fn accepts(&self, other: &DataType) -> bool {
+        match (self, other) {
+            (DataType::Array(a), DataType::Array(b)) => a.accepts(b),
+            _ => self == other || *self == DataType::Unknown || *other == DataType::Unknown,
+        }
+    }
+
+    /// The type of an element, for types that can be indexed or iterated over.
+    pub fn element_type(&self) -> Option<DataType> {
+        match self {
+            DataType::Array(element) => Some((**element).clone()),
+            DataType::Unknown => Some(DataType::Unknown),
+            _ => None,
+        }
+    }
 }
 
 
-pub fn type_from(value: String) -> DataType {
-    return match value.as_str() {
-        "int" => DataType::Int,
-        "string" => DataType::String,
-        "bool" => DataType::Bool,
-        "array" => DataType::Array,
-        "object" => DataType::Object,
-        _ => panic!("Unknown type {}", value),
+pub fn type_from(value: &str) -> Option<DataType> {
+    match value {
+        "int" => Some(DataType::Int),
+        "float" => Some(DataType::Float),
+        "string" => Some(DataType::String),
+        "bool" => Some(DataType::Bool),
+        "array" => Some(DataType::Array(Box::new(DataType::Unknown))),
+        "object" => Some(DataType::Object),
+        _ => None,
     }
 }
 
@@ -26,11 +54,17 @@ impl fmt::Display for DataType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             DataType::Int => write!(f, "int"),
+            DataType::Float => write!(f, "float"),
             DataType::String => write!(f, "string"),
             DataType::Bool => write!(f, "bool"),
             DataType::None => write!(f, "none"),
-            DataType::Array => write!(f, "array"),
+            DataType::Array(element) => match **element {
+                DataType::Unknown => write!(f, "array"),
+                _ => write!(f, "[]{}", element),
+            },
             DataType::Object => write!(f, "object"),
+            DataType::Named(name) => write!(f, "{}", name),
+            DataType::Unknown => write!(f, "unknown"),
         }
     }
 }
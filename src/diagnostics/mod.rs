@@ -0,0 +1,178 @@
+//! Structured compiler errors and warnings.
+//!
+//! The lexer, parser and type checker never panic on bad input; they build a
+//! [`Diagnostic`] and push it into a [`Diagnostics`] sink so that a compile can
+//! carry on and report everything it found.
+
+use crate::span::Span;
+use std::collections::HashSet;
+use std::fmt;
+
+pub mod render;
+
+pub use render::Renderer;
+
+/// Stable identifiers for every diagnostic the compiler can emit.
+pub mod codes {
+    // lexer
+    pub const UNKNOWN_CHARACTER: &str = "E0101";
+    pub const MALFORMED_NUMBER: &str = "E0102";
+    pub const NUMBER_OVERFLOW: &str = "E0103";
+    pub const UNTERMINATED_STRING: &str = "E0104";
+    pub const INVALID_ESCAPE: &str = "E0105";
+    pub const UNTERMINATED_INTERPOLATION: &str = "E0106";
+    pub const UNTERMINATED_COMMENT: &str = "E0107";
+
+    // parser
+    pub const UNEXPECTED_TOKEN: &str = "E0201";
+    pub const UNEXPECTED_EOF: &str = "E0202";
+    pub const UNKNOWN_TYPE: &str = "E0203";
+    pub const INVALID_INFIX: &str = "E0204";
+    pub const UNKNOWN_INFIX: &str = "E0205";
+    pub const UNUSED_DOC_COMMENT: &str = "W0201";
+
+    // type checker
+    pub const MISMATCHED_TYPES: &str = "E0301";
+    pub const UNKNOWN_FUNCTION: &str = "E0302";
+    pub const WRONG_ARGUMENT_COUNT: &str = "E0303";
+    pub const UNKNOWN_VARIABLE: &str = "E0304";
+    pub const MISSING_TYPE: &str = "E0305";
+    pub const OUTSIDE_OF_LOOP: &str = "E0306";
+    pub const NOT_ASSIGNABLE: &str = "E0307";
+    pub const UNKNOWN_STRUCT: &str = "E0308";
+    pub const MISSING_FIELD: &str = "E0309";
+    pub const UNKNOWN_FIELD: &str = "E0310";
+    pub const DUPLICATE_FIELD: &str = "E0311";
+    pub const NOT_A_STRUCT: &str = "E0312";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A secondary span with an explanation, e.g. "declared here".
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message, span)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, code, message, span)
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {} at {}", self.severity, self.code, self.message, self.span)?;
+        for label in &self.labels {
+            write!(f, "\n  {}: {}", label.span, label.message)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n  = note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects diagnostics as compilation goes along.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+    /// Everything in `items`, to spot duplicates without scanning it.
+    seen: HashSet<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// Records `diagnostic`, unless an identical one was already recorded.
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        if self.seen.insert(diagnostic.clone()) {
+            self.items.push(diagnostic);
+        }
+    }
+
+    pub fn extend(&mut self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        for diagnostic in diagnostics {
+            self.push(diagnostic);
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items.iter().any(Diagnostic::is_error)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.items.iter()
+    }
+
+    /// All recorded diagnostics, in source order.
+    pub fn into_vec(mut self) -> Vec<Diagnostic> {
+        self.items.sort_by_key(|diagnostic| diagnostic.span.start);
+        self.items
+    }
+}
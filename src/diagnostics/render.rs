@@ -0,0 +1,160 @@
+//! Turns diagnostics into rustc-style reports:
+//!
+//! ```text
+//! error[E0301]: mismatched types: int + string
+//!  --> main:1:13
+//!   |
+//! 1 | var a int = 1 + "x"
+//!   |             ^^^^^^^
+//!   |             - this is int
+//!   = note: ...
+//! ```
+
+use super::{Diagnostic, Severity};
+use crate::span::Span;
+use colored::*;
+
+pub struct Renderer<'a> {
+    file_name: &'a str,
+    source: &'a str,
+    line_starts: Vec<usize>,
+    color: bool,
+}
+
+/// One underline drawn beneath a source line.
+struct Marker<'m> {
+    line: usize,
+    from_col: usize,
+    to_col: usize,
+    primary: bool,
+    message: Option<&'m str>,
+}
+
+impl<'a> Renderer<'a> {
+    /// A renderer producing plain text, suitable for logs and tests.
+    pub fn new(file_name: &'a str, source: &'a str) -> Renderer<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Renderer { file_name, source, line_starts, color: false }
+    }
+
+    /// Turns ANSI colors on or off.
+    pub fn with_color(mut self, color: bool) -> Renderer<'a> {
+        self.color = color;
+        self
+    }
+
+    pub fn render_all(&self, diagnostics: &[Diagnostic]) -> String {
+        diagnostics.iter().map(|diagnostic| self.render(diagnostic)).collect::<Vec<_>>().join("\n")
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut markers = self.markers(diagnostic.span, true, None);
+        for label in &diagnostic.labels {
+            markers.extend(self.markers(label.span, false, Some(&label.message)));
+        }
+        let mut lines: Vec<usize> = markers.iter().map(|marker| marker.line).collect();
+        lines.sort();
+        lines.dedup();
+        let gutter_width = lines.last().copied().unwrap_or(1).to_string().len();
+        let gutter = " ".repeat(gutter_width);
+        let bar = self.paint_gutter("|");
+
+        let mut out = format!(
+            "{}{}\n",
+            self.paint_severity(diagnostic.severity, &format!("{}[{}]", diagnostic.severity, diagnostic.code)),
+            self.paint_bold(&format!(": {}", diagnostic.message)),
+        );
+        out += &format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            self.paint_gutter("-->"),
+            self.file_name,
+            diagnostic.span.line,
+            diagnostic.span.col,
+        );
+        out += &format!("{} {}\n", gutter, bar);
+        let mut previous_line = None;
+        for line in lines {
+            if let Some(previous) = previous_line
+                && line > previous + 1
+            {
+                out += &format!("{}\n", self.paint_gutter("..."));
+            }
+            previous_line = Some(line);
+            let text = self.line_text(line);
+            out += &format!("{} {} {}\n", self.paint_gutter(&format!("{:>width$}", line, width = gutter_width)), bar, text);
+            for marker in markers.iter().filter(|marker| marker.line == line) {
+                let (character, paint) = if marker.primary { ('^', diagnostic.severity) } else { ('-', Severity::Note) };
+                let underline: String = std::iter::repeat_n(character, marker.to_col.saturating_sub(marker.from_col).max(1)).collect();
+                let mut underline_line = format!("{}{}", " ".repeat(marker.from_col - 1), self.paint_severity(paint, &underline));
+                if let Some(message) = marker.message {
+                    underline_line += &format!(" {}", self.paint_severity(paint, message));
+                }
+                out += &format!("{} {} {}\n", gutter, bar, underline_line);
+            }
+        }
+        for note in &diagnostic.notes {
+            out += &format!("{} {} {}\n", gutter, self.paint_gutter("="), self.paint_bold(&format!("note: {}", note)));
+        }
+        out
+    }
+
+    /// Splits `span` into one marker per source line it covers. Columns are
+    /// counted in characters so underlines line up with the printed text.
+    fn markers<'m>(&self, span: Span, primary: bool, message: Option<&'m str>) -> Vec<Marker<'m>> {
+        let first_line = self.line_of(span.start);
+        let last_line = self.line_of(span.end.max(span.start + 1) - 1).max(first_line);
+        let mut markers = Vec::new();
+        for line in first_line..=last_line {
+            let line_start = self.line_starts[line - 1];
+            let text = self.line_text(line);
+            // a span may start on a `\r` that the printed text leaves out
+            let from = if line == first_line { (span.start - line_start).min(text.len()) } else { 0 };
+            let to = if line == last_line { (span.end - line_start).min(text.len()) } else { text.len() };
+            let from_col = text.get(..from).map_or(from, |prefix| prefix.chars().count()) + 1;
+            let to_col = text.get(..to.max(from)).map_or(to, |prefix| prefix.chars().count()) + 1;
+            markers.push(Marker {
+                line,
+                from_col,
+                to_col,
+                primary,
+                message: if line == last_line { message } else { None },
+            });
+        }
+        markers
+    }
+
+    fn line_of(&self, pos: usize) -> usize {
+        match self.line_starts.binary_search(&pos) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    fn line_text(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line - 1];
+        let end = self.line_starts.get(line).map_or(self.source.len(), |next| next - 1);
+        self.source[start..end].trim_end_matches('\r')
+    }
+
+    fn paint_severity(&self, severity: Severity, text: &str) -> String {
+        if !self.color {
+            return text.to_string();
+        }
+        match severity {
+            Severity::Error => text.red().bold().to_string(),
+            Severity::Warning => text.yellow().bold().to_string(),
+            Severity::Note => text.cyan().bold().to_string(),
+        }
+    }
+
+    fn paint_gutter(&self, text: &str) -> String {
+        if self.color { text.blue().bold().to_string() } else { text.to_string() }
+    }
+
+    fn paint_bold(&self, text: &str) -> String {
+        if self.color { text.bold().to_string() } else { text.to_string() }
+    }
+}
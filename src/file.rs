@@ -2,10 +2,10 @@ use std::collections::HashMap;
 
 use compiler_11::{
     lexer::token::TokenType,
-    parser::Parser,
-    ast::{FunctionDef, Variable, Expression, StructDef},
+    parser::{ParseResult, Parser},
+    ast::{FunctionDef, Variable, StructDef},
     data_type::DataType,
-    
+    diagnostics::{codes, Diagnostic, Diagnostics},
 };
 use crate::get_type::HasType;
 
@@ -13,105 +13,176 @@ use crate::get_type::HasType;
 
 pub struct File<'a> {
     pub name: String,
-    source: &'a str,
+    pub source: &'a str,
     pub functions: HashMap<String, FunctionDef<'a>>,
     pub variables: HashMap<String, Variable<'a>>,
     pub structs: HashMap<String, StructDef<'a>>,
+    /// Diagnostics that did not stop compilation, such as warnings.
+    pub warnings: Vec<Diagnostic>,
 }
 
 
 impl<'a> File<'a> {
-    pub fn parse(source: &'a str) -> File<'a> {
-        
+    /// Parses and type checks `source`.
+    pub fn compile(source: &'a str) -> Result<File<'a>, Vec<Diagnostic>> {
+        let mut file = File::parse(source)?;
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.extend(std::mem::take(&mut file.warnings));
+        file.validate_global_variable_types(&mut diagnostics);
+        file.validate_functions(&mut diagnostics);
+        if diagnostics.has_errors() {
+            return Err(diagnostics.into_vec());
+        }
+        file.warnings = diagnostics.into_vec();
+        Ok(file)
+    }
+
+    pub fn parse(source: &'a str) -> Result<File<'a>, Vec<Diagnostic>> {
         let mut parser = Parser::new(source);
-        let mut functions = HashMap::new();
-        let mut variables = HashMap::new();
-        let mut structs = HashMap::new();
+        let mut file = File {
+            name: "main".to_string(),
+            source,
+            functions: HashMap::new(),
+            variables: HashMap::new(),
+            structs: HashMap::new(),
+            warnings: Vec::new(),
+        };
         parser.tokenizer.eat_lines();
         while  parser.tokenizer.peek().is_some(){
-            let token_start_pos = parser.tokenizer.index;
-            let token= parser.tokenizer.next().unwrap();
-            match token.type_ {
-                TokenType::Keyword => {
-                    match token.value.as_str() {
-                        "func" => {
-                            let function = parser.parse_function();
-                            functions.insert(function.name.clone(), function);
-                        }
-                        "var" => {
-                            let variable = parser.parse_var();
-                            variables.insert(variable.name.clone(), variable);
-                        }
-                        "struct" => {
-                            let struct_ = parser.parse_struct();
-                            structs.insert(struct_.name.clone(), struct_);
-                        }
-                        _ => parser.tokenizer.show_user_error(token_start_pos, token_start_pos+token.value.len(), "not implemented".to_string())
+            if let Err(diagnostic) = file.parse_declaration(&mut parser) {
+                parser.report(*diagnostic);
+                parser.synchronize_declaration();
+            }
+            parser.tokenizer.eat_lines();
+        }
+        let diagnostics = std::mem::take(&mut parser.tokenizer.diagnostics);
+        if diagnostics.has_errors() {
+            return Err(diagnostics.into_vec());
+        }
+        file.warnings = diagnostics.into_vec();
+        Ok(file)
+    }
+
+    fn parse_declaration(&mut self, parser: &mut Parser<'a>) -> ParseResult<()> {
+        let doc = parser.take_doc_comments();
+        let token_start = parser.tokenizer.checkpoint();
+        let Some(token) = parser.tokenizer.next_in_statement() else {
+            if let Some((_, span)) = doc {
+                parser.report_unused_doc_comment(span);
+            }
+            return Ok(());
+        };
+        let doc = doc.map(|(text, _)| text);
+        match token.type_ {
+            TokenType::Keyword => {
+                match token.value.as_str() {
+                    "func" => {
+                        let function = FunctionDef { doc, ..parser.parse_function()? };
+                        self.functions.insert(function.name.clone(), function);
                     }
-                }
-                TokenType::Identifier => {
-                    if parser.tokenizer.optionally_expect_punctuation('(') {
-                        parser.tokenizer.index = token_start_pos;
-                        let function = parser.parse_function();
-                        functions.insert(function.name.clone(), function);
-                    } else if parser.tokenizer.optionally_expect_punctuation('{') {
-                        parser.tokenizer.index = token_start_pos;
-                        let struct_ = parser.parse_struct();
-                        structs.insert(struct_.name.clone(), struct_);
-                    } else {
-                        parser.tokenizer.show_user_error(token_start_pos, token_start_pos+token.value.len(), "not implemented".to_string())
+                    "var" => {
+                        let variable = Variable { doc, ..parser.parse_var()? };
+                        self.variables.insert(variable.name.clone(), variable);
+                    }
+                    "infix" => {
+                        let function = FunctionDef { doc, ..parser.parse_infix_function()? };
+                        self.functions.insert(function.name.clone(), function);
+                    }
+                    "struct" => {
+                        let struct_ = StructDef { doc, ..parser.parse_struct()? };
+                        self.structs.insert(struct_.name.clone(), struct_);
                     }
+                    _ => return Err(Box::new(expected_declaration(&token))),
                 }
-                _ => parser.tokenizer.show_user_error(token_start_pos, token_start_pos+token.value.len(), "not implemented".to_string())
             }
-            parser.tokenizer.eat_lines();
+            TokenType::Identifier => {
+                if parser.next_is_punctuation('(') {
+                    parser.tokenizer.restore(token_start);
+                    let function = FunctionDef { doc, ..parser.parse_function()? };
+                    self.functions.insert(function.name.clone(), function);
+                } else if parser.next_is_punctuation('{') {
+                    parser.tokenizer.restore(token_start);
+                    let struct_ = StructDef { doc, ..parser.parse_struct()? };
+                    self.structs.insert(struct_.name.clone(), struct_);
+                } else {
+                    return Err(Box::new(expected_declaration(&token)));
+                }
+            }
+            _ => return Err(Box::new(expected_declaration(&token))),
         }
-        File {
-            name: "main".to_string(),
-            source,
-            functions,
-            variables,
-            structs,
-        }   
+        parser.expect_terminator()
     }
 
 
 
-    pub fn validate_global_variable_types(&self) {
+    pub fn validate_global_variable_types(&self, diagnostics: &mut Diagnostics) {
         for variable in self.variables.values() {
             match &variable.value {
-                Some(value) => assert_eq!(variable.type_, value.get_type(self, &None), "Variable {} has type {} but value {}", variable.name, variable.type_, value.get_type(self, &None)),
-                None => {assert_ne!(variable.type_, DataType::None, "Variable {} has no type or default value to infer type", variable.name)}
+                Some(value) => {
+                    let value_type = value.get_type(self, None, diagnostics);
+                    if !variable.type_.accepts(&value_type) {
+                        diagnostics.push(Diagnostic::error(
+                            codes::MISMATCHED_TYPES,
+                            format!("Variable {} has type {} but value {}", variable.name, variable.type_, value_type),
+                            value.span(),
+                        ));
+                    }
+                }
+                None => {
+                    if variable.type_ == DataType::None {
+                        diagnostics.push(Diagnostic::error(
+                            codes::MISSING_TYPE,
+                            format!("Variable {} has no type or default value to infer type", variable.name),
+                            variable.span,
+                        ));
+                    }
+                }
             }
         }   
     }
 }
 
+fn expected_declaration(token: &compiler_11::lexer::token::Token) -> Diagnostic {
+    Diagnostic::error(
+        codes::UNEXPECTED_TOKEN,
+        format!("expected a function, variable or struct declaration, found `{}`", token.value),
+        token.span,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use compiler_11::data_type::DataType;
     use compiler_11::ast::{Expression, FunctionCall, OperatorUse, ValidInFunctionBody};
+    use compiler_11::ast::structure::VarReference;
     use compiler_11::lexer::token::Token;
+    use compiler_11::span::Span;
 
     #[test]
     fn test_parse_file() {
         let code = "func add(a int, b int,): int { return a + b } \n var result int = add(1, 2,)";
-        let file = File::parse(code);
+        let file = File::parse(code).unwrap();
         let parsed_add_function = file.functions.get("add").unwrap();
 
         let expected_add_function = FunctionDef {
+            doc: None,
+            infix: None,
             name: "add".to_string(),
             args: vec![
                 Variable {
+                    doc: None,
                     name: "a".to_string(),
                     type_: DataType::Int,
                     value: None,
+                    span: Span::default(),
                 },
                 Variable {
+                    doc: None,
                     name: "b".to_string(),
                     type_: DataType::Int,
                     value: None,
+                    span: Span::default(),
                 },
             ],
             return_type: DataType::Int,
@@ -119,40 +190,439 @@ mod tests {
                 ValidInFunctionBody::Return(
                     Expression::OperatorUse(
                         OperatorUse{
-                            left: Box::new(Expression::Token(Token {
-                                type_: TokenType::Identifier,
-                                value: "a".to_string(),
+                            left: Box::new(Expression::VarReference(VarReference {
+                                name: "a".to_string(),
+                                referring_to: None,
+                                span: Span::default(),
                             })),
                             operator: "+".to_string(),
-                            right: Box::new(Expression::Token(Token {
-                                type_: TokenType::Identifier,
-                                value: "b".to_string(),
-                            }))
+                            right: Box::new(Expression::VarReference(VarReference {
+                                name: "b".to_string(),
+                                referring_to: None,
+                                span: Span::default(),
+                            })),
+                            span: Span::default(),
                         }
                     ),
                 ),
             ],
+            span: Span::default(),
         };
 
 
         let expected_result_variable = Variable {
+            doc: None,
             name: "result".to_string(),
             type_: DataType::Int,
             value: Some(Expression::FunctionCall(FunctionCall {
                 name: "add".to_string(),
                 args: vec![
-                    Expression::Token(Token {
-                        type_: TokenType::Number,
-                        value: "1".to_string(),
-                    }),
-                    Expression::Token(Token {
-                        type_: TokenType::Number,
-                        value: "2".to_string(),
-                    }),
+                    Expression::Token(Token::new(TokenType::Number, "1", Span::default())),
+                    Expression::Token(Token::new(TokenType::Number, "2", Span::default())),
                 ],
+                span: Span::default(),
             })),
+            span: Span::default(),
         };
         assert_eq!(parsed_add_function, &expected_add_function);
         assert_eq!(file.variables.get("result").unwrap(), &expected_result_variable);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_compile_reports_every_type_error() {
+        let code = "var a int = \"text\"\nfunc f(x int): string { return x }\nvar b int = missing(1)";
+        let diagnostics = File::compile(code).err().unwrap();
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec![codes::MISMATCHED_TYPES, codes::MISMATCHED_TYPES, codes::UNKNOWN_FUNCTION]);
+        assert_eq!((diagnostics[0].span.line, diagnostics[0].span.col), (1, 13));
+        assert_eq!((diagnostics[1].span.line, diagnostics[1].span.col), (2, 32));
+        assert_eq!((diagnostics[2].span.line, diagnostics[2].span.col), (3, 13));
+    }
+
+    #[test]
+    fn test_parse_error_is_returned() {
+        let diagnostics = File::parse("var a int = 1\n)").err().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, codes::UNEXPECTED_TOKEN);
+        assert_eq!(diagnostics[0].span.line, 2);
+    }
+
+    #[test]
+    fn test_parse_reports_every_syntax_error() {
+        let code = "var a int = )
+func f(x int { return x }
+var b integer = 1
+func g(): int {
+    var c int = (1 + 2
+    return ]
+    var d int = 2
+}
+struct S {
+    name string
+    age
+}
+var e int = 3 +";
+        let diagnostics = File::parse(code).err().unwrap();
+        let lines: Vec<_> = diagnostics.iter().map(|d| d.span.line).collect();
+        assert_eq!(lines, vec![1, 2, 3, 5, 6, 11, 13]);
+        assert_eq!(diagnostics[1].message, "expected `)`, found `{`");
+        assert_eq!(diagnostics[2].code, codes::UNKNOWN_TYPE);
+        assert_eq!(diagnostics[6].code, codes::UNEXPECTED_EOF);
+    }
+
+    #[test]
+    fn test_if_condition_must_be_bool() {
+        let code = "func f(n int, ok bool): int {\n    if ok { return 1 } else if n { return 2 }\n    return 3\n}";
+        let diagnostics = File::compile(code).err().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "if condition must be bool but got int");
+        assert_eq!((diagnostics[0].span.line, diagnostics[0].span.col), (2, 32));
+    }
+
+    #[test]
+    fn test_if_blocks_get_their_own_scope() {
+        let code = "func f(ok bool): int {
+    var outer int = 1
+    if ok {
+        var inner int = outer
+        if ok { return inner }
+    } else {
+        return inner
+    }
+    return inner
+}";
+        let diagnostics = File::compile(code).err().unwrap();
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.code, d.span.line)).collect();
+        assert_eq!(lines, vec![(codes::UNKNOWN_VARIABLE, 7), (codes::UNKNOWN_VARIABLE, 9)]);
+    }
+
+    #[test]
+    fn test_for_loop_variable_is_typed_from_the_array() {
+        let code = "func total(names []string, go bool): int {
+    for name in names {
+        return name
+    }
+    for n in [1, 2] {
+        return n
+    }
+    for x in 3 {
+        return x
+    }
+    while go { return 0 }
+    return 0
+}";
+        let diagnostics = File::compile(code).err().unwrap();
+        let messages: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.message.as_str())).collect();
+        assert_eq!(messages, vec![
+            (3, "in function total type of return statement string does not match return type int"),
+            (8, "can only loop over an array but got int"),
+        ]);
+    }
+
+    #[test]
+    fn test_break_and_continue_must_be_inside_a_loop() {
+        let code = "func f(go bool) {
+    break
+    while go {
+        if go { continue }
+        break
+    }
+    if go { continue }
+}";
+        let diagnostics = File::compile(code).err().unwrap();
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.code, d.span.line)).collect();
+        assert_eq!(lines, vec![(codes::OUTSIDE_OF_LOOP, 2), (codes::OUTSIDE_OF_LOOP, 7)]);
+        assert_eq!(diagnostics[1].message, "`continue` outside of a loop");
+    }
+
+    #[test]
+    fn test_assignment_is_checked_against_the_target() {
+        let code = "var total int = 0
+func f(names []string, flag bool) {
+    total += 1
+    names[0] = \"a\"
+    total = \"a\"
+    names[0] -= \"b\"
+    f(names, flag) = 1
+    flag += flag
+}";
+        let diagnostics = File::compile(code).err().unwrap();
+        let messages: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.code, d.message.as_str())).collect();
+        assert_eq!(messages, vec![
+            (5, codes::MISMATCHED_TYPES, "expected int but got string in assignment"),
+            (6, codes::MISMATCHED_TYPES, "cannot use `-=` on string"),
+            (7, codes::NOT_ASSIGNABLE, "invalid left-hand side of assignment"),
+            (8, codes::MISMATCHED_TYPES, "cannot use `+=` on bool"),
+        ]);
+    }
+
+    #[test]
+    fn test_comparisons_and_logic_are_bool() {
+        let code = "var small bool = 1 < 2
+var same bool = \"a\" == \"b\"
+var either bool = small || false
+var sum int = 1 <= 2
+var mixed bool = 1 == \"a\"
+var bad bool = 1 || true";
+        let diagnostics = File::compile(code).err().unwrap();
+        let messages: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.message.as_str())).collect();
+        assert_eq!(messages, vec![
+            (4, "Variable sum has type int but value bool"),
+            (5, "mismatched types: int == string"),
+            (6, "`||` needs bool operands but got int"),
+        ]);
+    }
+
+    #[test]
+    fn test_bool_literals_drive_conditions() {
+        let code = "func f(): int {\n    while true { if 1 > 0 || false { return 1 } }\n    return 0\n}";
+        assert!(File::compile(code).is_ok());
+    }
+
+    #[test]
+    fn test_unary_operator_types() {
+        let code = "var a int = -1
+var b bool = !(1 < 2)
+var c int = -true
+var d bool = !a";
+        let diagnostics = File::compile(code).err().unwrap();
+        let messages: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.message.as_str())).collect();
+        assert_eq!(messages, vec![
+            (3, "cannot apply unary `-` to bool"),
+            (4, "cannot apply unary `!` to int"),
+        ]);
+    }
+
+    #[test]
+    fn test_arithmetic_needs_numbers() {
+        let code = "var sum int = 1 + 2 * 3 % 2
+var name string = \"a\" + \"b\"
+var a bool = true + false
+var b bool = true * false
+var c string = \"a\" - \"b\"
+var d []int = [1] + [2]";
+        let diagnostics = File::compile(code).err().unwrap();
+        let messages: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.code, d.message.as_str())).collect();
+        assert_eq!(messages, vec![
+            (3, codes::MISMATCHED_TYPES, "cannot use `+` on bool"),
+            (4, codes::MISMATCHED_TYPES, "cannot use `*` on bool"),
+            (5, codes::MISMATCHED_TYPES, "cannot use `-` on string"),
+            (6, codes::MISMATCHED_TYPES, "cannot use `+` on []int"),
+        ]);
+    }
+
+    #[test]
+    fn test_int_and_float_do_not_mix() {
+        let code = "var half float = 1.0 / 2.0
+var neg float = -half
+var big bool = half > 0.25
+var mixed float = half + 1
+var wrong int = 2.5";
+        let diagnostics = File::compile(code).err().unwrap();
+        let messages: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.message.as_str())).collect();
+        assert_eq!(messages, vec![
+            (4, "mismatched types: float + int"),
+            (5, "Variable wrong has type int but value float"),
+        ]);
+        assert_eq!(diagnostics[0].notes, vec!["int and float are never converted into each other implicitly".to_string()]);
+    }
+
+    #[test]
+    fn test_interpolations_are_strings_of_simple_values() {
+        let code = "var name string = \"bob\"
+var greeting string = \"hi {name}, {1 + 1} {1.5} {true}\"
+var count int = \"{name}\"
+var listed string = \"{[1, 2]} {missing}\"";
+        let diagnostics = File::compile(code).err().unwrap();
+        let messages: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.message.as_str())).collect();
+        assert_eq!(messages, vec![
+            (3, "Variable count has type int but value string"),
+            (4, "cannot interpolate a value of type []int into a string"),
+            (4, "cannot find variable `missing`"),
+        ]);
+    }
+
+    #[test]
+    fn test_comments_are_ignored_by_the_compiler() {
+        let code = "// adds things
+func add(a int, /* second */ b int): int {
+    /* a block
+       over lines */
+    return a + b // done
+}
+var total int = add(1, 2) // 3";
+        let file = File::compile(code).unwrap();
+        assert_eq!(file.functions.get("add").unwrap().body.len(), 1);
+    }
+
+    #[test]
+    fn test_doc_comments_attach_to_declarations() {
+        let code = "/// Adds two numbers.
+///
+/// Overflow wraps.
+func add(a int, b int): int {
+    /// not documenting anything
+    return a + b
+}
+
+/// A person.
+struct Person {
+    /// Their full name.
+    name string
+    age int
+    /// Says hello.
+    func greet() {}
+}
+
+/// The answer.
+var answer int = 42";
+        let file = File::compile(code).unwrap();
+        assert_eq!(file.functions["add"].doc.as_deref(), Some("Adds two numbers.\n\nOverflow wraps."));
+        let person = &file.structs["Person"];
+        assert_eq!(person.doc.as_deref(), Some("A person."));
+        assert_eq!(person.fields[0].doc.as_deref(), Some("Their full name."));
+        assert_eq!(person.fields[1].doc, None);
+        assert_eq!(person.methods[0].doc.as_deref(), Some("Says hello."));
+        assert_eq!(file.variables["answer"].doc.as_deref(), Some("The answer."));
+        let warnings: Vec<_> = file.warnings.iter().map(|d| (d.code, d.span.line)).collect();
+        assert_eq!(warnings, vec![(codes::UNUSED_DOC_COMMENT, 5)]);
+    }
+
+    #[test]
+    fn test_only_declared_infix_functions_go_between_operands() {
+        let code = "var big bool = 10 is_greater_than 9
+var typo bool = 10 is_greter_than 9
+
+infix func is_greater_than(a int, b int): bool { return a > b }
+infix func three(a int, b int, c int): int { return a }";
+        let diagnostics = File::parse(code).err().unwrap();
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.code, d.span.line, d.message.as_str())).collect();
+        assert_eq!(found, vec![
+            (codes::UNKNOWN_INFIX, 2, "`is_greter_than` is not an infix function"),
+            (codes::INVALID_INFIX, 5, "infix function `three` must take exactly two arguments, but takes 3"),
+        ]);
+    }
+
+    #[test]
+    fn test_infix_calls_are_type_checked() {
+        let code = "infix(8) func times(a int, b int): int { return a * b }
+var ok int = 1 + 2 times 3
+var bad int = 1 times \"two\"";
+        let diagnostics = File::compile(code).err().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].code, diagnostics[0].span.line), (codes::MISMATCHED_TYPES, 3));
+    }
+
+    #[test]
+    fn test_structs_can_be_used_as_types() {
+        let code = "func birthday(p Person): Person { return Person{name: p_name, age: 1} }
+var p_name string = \"Ada\"
+var ada Person = Person{name: \"Ada\", age: 36}
+var older Person = birthday(ada)
+var wrong int = ada
+
+struct Person {
+    name string
+    age int
+}";
+        let diagnostics = File::compile(code).err().unwrap();
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.code, d.span.line)).collect();
+        assert_eq!(found, vec![(codes::MISMATCHED_TYPES, 5)]);
+        let diagnostics = File::parse("var p Persn").err().unwrap();
+        assert_eq!(diagnostics[0].message, "unknown type `Persn`");
+    }
+
+    #[test]
+    fn test_struct_literals_are_checked_against_the_struct() {
+        let code = "struct Person {
+    name string
+    age int
+    nickname string = \"none\"
+}
+var a Person = Person{name: \"a\"}
+var b Person = Person{name: \"b\", age: 3, height: 2}
+var c Person = Person{name: 3, age: 3}
+var d Person = Persn{name: \"d\"}
+var e Person = Person{name: \"e\", age: 1, name: \"f\"}";
+        let diagnostics = File::compile(code).err().unwrap();
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.code, d.span.line, d.message.as_str())).collect();
+        assert_eq!(found, vec![
+            (codes::MISSING_FIELD, 6, "missing field `age` in `Person`"),
+            (codes::UNKNOWN_FIELD, 7, "struct `Person` has no field `height`"),
+            (codes::MISMATCHED_TYPES, 8, "expected string but got int on field `name` of struct Person"),
+            (codes::UNKNOWN_STRUCT, 9, "cannot find struct `Persn`"),
+            (codes::DUPLICATE_FIELD, 10, "field `name` is given more than once"),
+        ]);
+    }
+
+    #[test]
+    fn test_fields_and_methods_are_reached_with_a_dot() {
+        let code = "struct Person {
+    name string
+    age int
+    func greet(other Person): string { return \"hi\" }
+}
+func f(p Person) {
+    var name string = p.name
+    var greeting string = p.greet(p)
+    var first string = p.greet(p).name
+    var height int = p.height
+    var age string = p.age
+    p.greet(1)
+    p.wave()
+    var bad int = p.age.value
+    var method string = p.greet
+}";
+        let diagnostics = File::compile(code).err().unwrap();
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.code, d.message.as_str())).collect();
+        assert_eq!(found, vec![
+            (9, codes::NOT_A_STRUCT, "cannot use `.` on a value of type string"),
+            (10, codes::UNKNOWN_FIELD, "struct `Person` has no field `height`"),
+            (11, codes::MISMATCHED_TYPES, "expected string but got int on variable age"),
+            (12, codes::MISMATCHED_TYPES, "expected Person but got int on arg 1 when trying to call method greet"),
+            (13, codes::UNKNOWN_FUNCTION, "struct `Person` has no method `wave`"),
+            (14, codes::NOT_A_STRUCT, "cannot use `.` on a value of type int"),
+            (15, codes::UNKNOWN_FIELD, "struct `Person` has no field `greet`"),
+        ]);
+    }
+
+    #[test]
+    fn test_struct_fields_can_be_assigned_to() {
+        let code = "struct Person {
+    age int
+    scores []int
+    func older(): Person { return Person{age: 1, scores: [1]} }
+}
+func f(p Person, people []Person): int {
+    p.age += 1
+    people[0].age = 2
+    p.scores[0] = 3
+    p.age = \"old\"
+    p.older().age = 3
+    p.older().scores[0] = 4
+    return p.scores[0] + p.older().scores[1]
+}";
+        let diagnostics = File::compile(code).err().unwrap();
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.span.line, d.code)).collect();
+        assert_eq!(found, vec![(10, codes::MISMATCHED_TYPES), (11, codes::NOT_ASSIGNABLE), (12, codes::NOT_ASSIGNABLE)]);
+    }
+
+    #[test]
+    fn test_crlf_line_breaks_compile() {
+        let file = File::compile("var a int = 1\r\nvar b int = 2\r\n").unwrap();
+        assert_eq!(file.variables.len(), 2);
+    }
+
+    #[test]
+    fn test_doc_comments_after_code_are_unused() {
+        let code = "var a int = 1 /// the a
+func f(): int {
+    return a /// note
+}
+var b string = \"\\n\" /// doc";
+        let file = File::compile(code).unwrap();
+        assert_eq!(file.variables["a"].doc, None);
+        let warnings: Vec<_> = file.warnings.iter().map(|d| (d.code, d.span.line)).collect();
+        assert_eq!(warnings, vec![(codes::UNUSED_DOC_COMMENT, 1), (codes::UNUSED_DOC_COMMENT, 3), (codes::UNUSED_DOC_COMMENT, 5)]);
+    }
+}
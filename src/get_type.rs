@@ -1,6 +1,4 @@
-use std::option;
-
-use compiler_11::{ast::{Expression, FunctionCall, OperatorUse}, data_type::{self, DataType}, lexer::token::TokenType};
+use compiler_11::{ast::{structure::Object, Expression, FieldAccess, FunctionCall, FunctionDef, InterpolationPart, MethodCall, OperatorUse, StructDef, UnaryOp}, data_type::DataType, diagnostics::{codes, Diagnostic, Diagnostics}, lexer::token::TokenType, span::Span};
 
 use crate::{file::File, in_function_scope_validation::find_var_type_from_local_scope, scope_placement_info::ScopePlacementInfo};
 
@@ -9,73 +7,322 @@ use crate::{file::File, in_function_scope_validation::find_var_type_from_local_s
 
 
 pub trait HasType<'compilation_unit> {
-    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: &'compilation_unit Option<ScopePlacementInfo<'compilation_unit>>) -> DataType;
+    /// Works out the type of `self`, reporting any type errors found on the way
+    /// into `diagnostics`. Ill-typed expressions come back as `DataType::Unknown`.
+    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType;
 }
 
 
 
 impl<'compilation_unit>HasType<'compilation_unit> for OperatorUse<'compilation_unit>  {
-    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: &'compilation_unit Option<ScopePlacementInfo<'compilation_unit>>) -> DataType {
-        let lefts_type = self.left.get_type(file, scope_placement_info);
-        let rights_type = self.right.get_type(file, scope_placement_info);
-        if lefts_type != rights_type {
-            panic!("Type mismatch: {} and {}", lefts_type, rights_type);
+    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType {
+        let lefts_type = self.left.get_type(file, scope_placement_info, diagnostics);
+        let rights_type = self.right.get_type(file, scope_placement_info, diagnostics);
+        if matches!(self.operator.as_str(), "&&" | "||") {
+            // both sides are checked on their own so each gets its own error
+            for (operand, operand_type) in [(&self.left, &lefts_type), (&self.right, &rights_type)] {
+                if !DataType::Bool.accepts(operand_type) {
+                    diagnostics.push(Diagnostic::error(
+                        codes::MISMATCHED_TYPES,
+                        format!("`{}` needs bool operands but got {}", self.operator, operand_type),
+                        operand.span(),
+                    ));
+                }
+            }
+            return DataType::Bool;
+        }
+        if !lefts_type.accepts(&rights_type) {
+            let mut diagnostic = Diagnostic::error(codes::MISMATCHED_TYPES, format!("mismatched types: {} {} {}", lefts_type, self.operator, rights_type), self.span)
+                .with_label(self.left.span(), format!("this is {}", lefts_type))
+                .with_label(self.right.span(), format!("this is {}", rights_type));
+            if matches!((&lefts_type, &rights_type), (DataType::Int, DataType::Float) | (DataType::Float, DataType::Int)) {
+                diagnostic = diagnostic.with_note("int and float are never converted into each other implicitly");
+            }
+            diagnostics.push(diagnostic);
+            return DataType::Unknown;
+        }
+        let operands_allowed = match self.operator.as_str() {
+            "+" => matches!(lefts_type, DataType::Int | DataType::Float | DataType::String | DataType::Unknown),
+            "-" | "*" | "/" | "%" => matches!(lefts_type, DataType::Int | DataType::Float | DataType::Unknown),
+            _ => true,
+        };
+        if !operands_allowed {
+            diagnostics.push(
+                Diagnostic::error(codes::MISMATCHED_TYPES, format!("cannot use `{}` on {}", self.operator, lefts_type), self.span)
+                    .with_label(self.left.span(), format!("this is {}", lefts_type)),
+            );
+            return DataType::Unknown;
+        }
+        match self.operator.as_str() {
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => DataType::Bool,
+            _ => lefts_type,
         }
-        lefts_type
     }
 }
 
 
 
 
+impl<'compilation_unit> HasType<'compilation_unit> for UnaryOp<'compilation_unit> {
+    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType {
+        let operand_type = self.operand.get_type(file, scope_placement_info, diagnostics);
+        let result_type = match (self.operator.as_str(), &operand_type) {
+            ("!", _) => DataType::Bool,
+            (_, DataType::Float) => DataType::Float,
+            _ => DataType::Int,
+        };
+        if !result_type.accepts(&operand_type) {
+            diagnostics.push(
+                Diagnostic::error(codes::MISMATCHED_TYPES, format!("cannot apply unary `{}` to {}", self.operator, operand_type), self.span)
+                    .with_label(self.operand.span(), format!("this is {}", operand_type)),
+            );
+        }
+        result_type
+    }
+}
+
 impl<'compilation_unit> HasType<'compilation_unit> for FunctionCall<'compilation_unit> {
-    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: &'compilation_unit Option<ScopePlacementInfo<'compilation_unit>>) -> DataType {
-        let function = file.functions.get(&self.name).expect(format!("Function {} not found", self.name).as_str());
-        if self.args.len() != function.args.len() {
-            panic!("Argument count mismatch: {} and {} when trying to call function {}", self.args.len(), function.args.len(), self.name);
+    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType {
+        let Some(function) = file.functions.get(&self.name) else {
+            diagnostics.push(Diagnostic::error(codes::UNKNOWN_FUNCTION, format!("cannot find function `{}`", self.name), self.span));
+            return DataType::Unknown;
+        };
+        check_arguments("function", &self.args, self.span, function, file, scope_placement_info, diagnostics);
+        function.return_type.clone() //@optimize
+    }
+}
+
+impl<'compilation_unit> HasType<'compilation_unit> for FieldAccess<'compilation_unit> {
+    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType {
+        let Some(struct_) = struct_of(&self.object, file, scope_placement_info, diagnostics) else {
+            return DataType::Unknown;
+        };
+        if let Some(field) = struct_.fields.iter().find(|field| field.name == self.field) {
+            return field.type_.clone();
         }
-        for (i, arg) in self.args.iter().enumerate() {
-            let func_arg = &function.args[i];
-            if arg.get_type(file, scope_placement_info) != func_arg.type_ {
-                panic!("Type mismatch: expected {} but got {} on arg {} when trying to call function {}", func_arg.type_, arg.get_type(file, scope_placement_info), i+1, self.name);
+        let mut diagnostic = Diagnostic::error(codes::UNKNOWN_FIELD, format!("struct `{}` has no field `{}`", struct_.name, self.field), self.span)
+            .with_label(struct_.span, "defined here");
+        if struct_.methods.iter().any(|method| method.name == self.field) {
+            diagnostic = diagnostic.with_note(format!("`{}` is a method, call it as `{}()`", self.field, self.field));
+        }
+        diagnostics.push(diagnostic);
+        DataType::Unknown
+    }
+}
+
+impl<'compilation_unit> HasType<'compilation_unit> for MethodCall<'compilation_unit> {
+    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType {
+        let Some(struct_) = struct_of(&self.object, file, scope_placement_info, diagnostics) else {
+            for arg in &self.args {
+                arg.get_type(file, scope_placement_info, diagnostics);
             }
+            return DataType::Unknown;
+        };
+        let Some(method) = struct_.methods.iter().find(|method| method.name == self.method) else {
+            diagnostics.push(
+                Diagnostic::error(codes::UNKNOWN_FUNCTION, format!("struct `{}` has no method `{}`", struct_.name, self.method), self.span)
+                    .with_label(struct_.span, "defined here"),
+            );
+            return DataType::Unknown;
+        };
+        check_arguments("method", &self.args, self.span, method, file, scope_placement_info, diagnostics);
+        method.return_type.clone()
+    }
+}
+
+impl<'compilation_unit> HasType<'compilation_unit> for Object<'compilation_unit> {
+    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType {
+        if self.name == "anonymous" {
+            for field in &self.fields {
+                if let Some(value) = &field.value {
+                    value.get_type(file, scope_placement_info, diagnostics);
+                }
+            }
+            return DataType::Object;
         }
-        function.return_type.clone() //@optimize
+        let Some(struct_) = file.structs.get(&self.name) else {
+            diagnostics.push(Diagnostic::error(codes::UNKNOWN_STRUCT, format!("cannot find struct `{}`", self.name), self.span));
+            return DataType::Unknown;
+        };
+        for (i, field) in self.fields.iter().enumerate() {
+            let value_type = match &field.value {
+                Some(value) => value.get_type(file, scope_placement_info, diagnostics),
+                None => DataType::Unknown,
+            };
+            if self.fields[..i].iter().any(|earlier| earlier.name == field.name) {
+                diagnostics.push(Diagnostic::error(codes::DUPLICATE_FIELD, format!("field `{}` is given more than once", field.name), field.span));
+                continue;
+            }
+            let Some(struct_field) = struct_.fields.iter().find(|struct_field| struct_field.name == field.name) else {
+                diagnostics.push(
+                    Diagnostic::error(codes::UNKNOWN_FIELD, format!("struct `{}` has no field `{}`", self.name, field.name), field.span)
+                        .with_label(struct_.span, "defined here"),
+                );
+                continue;
+            };
+            if !struct_field.type_.accepts(&value_type) {
+                diagnostics.push(
+                    Diagnostic::error(
+                        codes::MISMATCHED_TYPES,
+                        format!("expected {} but got {} on field `{}` of struct {}", struct_field.type_, value_type, field.name, self.name),
+                        field.span,
+                    )
+                    .with_label(struct_field.span, "field declared here"),
+                );
+            }
+        }
+        // fields with a default value may be left out
+        for struct_field in &struct_.fields {
+            if struct_field.value.is_none() && !self.fields.iter().any(|field| field.name == struct_field.name) {
+                diagnostics.push(
+                    Diagnostic::error(codes::MISSING_FIELD, format!("missing field `{}` in `{}`", struct_field.name, self.name), self.span)
+                        .with_label(struct_field.span, "field declared here"),
+                );
+            }
+        }
+        DataType::Named(self.name.clone())
     }
 }
 
 impl<'compilation_unit> HasType<'compilation_unit> for Expression<'compilation_unit> {
-    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: &'compilation_unit Option<ScopePlacementInfo<'compilation_unit>>) -> DataType {
+    fn get_type(&self, file: &File<'compilation_unit>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType {
         match self {
-            Expression::OperatorUse(op) => op.get_type(file, scope_placement_info),
+            Expression::OperatorUse(op) => op.get_type(file, scope_placement_info, diagnostics),
+            Expression::UnaryOp(op) => op.get_type(file, scope_placement_info, diagnostics),
             Expression::Token(token) => match token.type_ {
                 TokenType::Number => DataType::Int,
+                TokenType::Float => DataType::Float,
                 TokenType::String => DataType::String,
-                _ => panic!("Unknown token type: {}", token.type_)
+                TokenType::Bool => DataType::Bool,
+                _ => {
+                    diagnostics.push(Diagnostic::error(codes::MISMATCHED_TYPES, format!("{} `{}` has no type", token.type_, token.value), token.span));
+                    DataType::Unknown
+                }
+            },
+            Expression::Interpolation(interpolation) => {
+                for part in &interpolation.parts {
+                    let InterpolationPart::Expression(expression) = part else {
+                        continue;
+                    };
+                    let part_type = expression.get_type(file, scope_placement_info, diagnostics);
+                    if !matches!(part_type, DataType::Int | DataType::Float | DataType::String | DataType::Bool | DataType::Unknown) {
+                        diagnostics.push(
+                            Diagnostic::error(codes::MISMATCHED_TYPES, format!("cannot interpolate a value of type {} into a string", part_type), expression.span())
+                                .with_note("only int, float, string and bool values can be interpolated"),
+                        );
+                    }
+                }
+                DataType::String
             },
             Expression::FunctionCall(call) => {
-                call.get_type(file, scope_placement_info)
+                call.get_type(file, scope_placement_info, diagnostics)
             },
             Expression::VarReference(reference) => {
-                if scope_placement_info.is_some() {
-                    let scope_placement_info = scope_placement_info.as_ref().unwrap();
-                    let variable_type = find_var_type_from_local_scope(&reference.name, scope_placement_info);
-                    if variable_type.is_some() {
-                        return variable_type.unwrap();
+                variable_type(&reference.name, reference.span, file, scope_placement_info, diagnostics)
+            },
+            Expression::Array(array) => {
+                let mut elements = array.elements.iter();
+                let Some(first) = elements.next() else {
+                    return DataType::Array(Box::new(DataType::Unknown));
+                };
+                let element_type = first.get_type(file, scope_placement_info, diagnostics);
+                for element in elements {
+                    let other_type = element.get_type(file, scope_placement_info, diagnostics);
+                    if !element_type.accepts(&other_type) {
+                        diagnostics.push(
+                            Diagnostic::error(codes::MISMATCHED_TYPES, format!("array elements must all be {} but got {}", element_type, other_type), element.span())
+                                .with_label(first.span(), format!("this is {}", element_type)),
+                        );
                     }
                 }
-                let variable = file.variables.get(&reference.name)
-                    .unwrap_or_else(|| panic!("Variable {} not found", reference.name));
-                variable.type_.clone()
+                DataType::Array(Box::new(element_type))
             },
-            Expression::Array(array) => DataType::Array,
-            Expression::Object(object) => DataType::Object,
+            Expression::Object(object) => object.get_type(file, scope_placement_info, diagnostics),
+            Expression::FieldAccess(access) => access.get_type(file, scope_placement_info, diagnostics),
+            Expression::MethodCall(call) => call.get_type(file, scope_placement_info, diagnostics),
             Expression::Subscript(subscript) => {
-                let variable = file.variables.get(&subscript.name)
-                    .unwrap_or_else(|| panic!("Variable {} not found", subscript.name));
-                variable.type_.clone()
+                let index_type = subscript.arg.get_type(file, scope_placement_info, diagnostics);
+                if !DataType::Int.accepts(&index_type) {
+                    diagnostics.push(Diagnostic::error(codes::MISMATCHED_TYPES, format!("array index must be int but got {}", index_type), subscript.arg.span()));
+                }
+                let subscripted_type = subscript.object.get_type(file, scope_placement_info, diagnostics);
+                subscripted_type.element_type().unwrap_or_else(|| {
+                    diagnostics.push(Diagnostic::error(codes::MISMATCHED_TYPES, format!("cannot index into a value of type {}", subscripted_type), subscript.object.span()));
+                    DataType::Unknown
+                })
             },
-            // _ => panic!("Unknown expression type: {}", self),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Checks the arguments of a call to `function` against its parameters.
+/// `kind` is what the error messages call it, a "function" or a "method".
+fn check_arguments<'compilation_unit>(
+    kind: &str,
+    args: &[Expression<'compilation_unit>],
+    span: Span,
+    function: &FunctionDef<'compilation_unit>,
+    file: &File<'compilation_unit>,
+    scope_placement_info: Option<&ScopePlacementInfo<'_>>,
+    diagnostics: &mut Diagnostics,
+) {
+    if args.len() != function.args.len() {
+        diagnostics.push(
+            Diagnostic::error(
+                codes::WRONG_ARGUMENT_COUNT,
+                format!("{} `{}` takes {} arguments but {} were given", kind, function.name, function.args.len(), args.len()),
+                span,
+            )
+            .with_label(function.span, "defined here"),
+        );
+    }
+    for (i, (arg, func_arg)) in args.iter().zip(function.args.iter()).enumerate() {
+        let arg_type = arg.get_type(file, scope_placement_info, diagnostics);
+        if !func_arg.type_.accepts(&arg_type) {
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::MISMATCHED_TYPES,
+                    format!("expected {} but got {} on arg {} when trying to call {} {}", func_arg.type_, arg_type, i+1, kind, function.name),
+                    arg.span(),
+                )
+                .with_label(func_arg.span, "parameter declared here"),
+            );
+        }
+    }
+}
+
+/// The struct that `object` is an instance of, for reaching into it with `.`.
+/// `None` when it is not a struct, which has been reported unless the type
+/// was already unknown.
+fn struct_of<'file, 'compilation_unit>(
+    object: &Expression<'compilation_unit>,
+    file: &'file File<'compilation_unit>,
+    scope_placement_info: Option<&ScopePlacementInfo<'_>>,
+    diagnostics: &mut Diagnostics,
+) -> Option<&'file StructDef<'compilation_unit>> {
+    match object.get_type(file, scope_placement_info, diagnostics) {
+        DataType::Named(name) => file.structs.get(&name),
+        DataType::Unknown => None,
+        other => {
+            diagnostics.push(Diagnostic::error(codes::NOT_A_STRUCT, format!("cannot use `.` on a value of type {}", other), object.span()));
+            None
+        }
+    }
+}
+
+/// The type of the variable `name`, looked up in the enclosing function first
+/// and then among the globals.
+fn variable_type(name: &str, span: Span, file: &File<'_>, scope_placement_info: Option<&ScopePlacementInfo<'_>>, diagnostics: &mut Diagnostics) -> DataType {
+    if let Some(scope_placement_info) = scope_placement_info
+        && let Some(variable_type) = find_var_type_from_local_scope(name, scope_placement_info)
+    {
+        return variable_type;
+    }
+    match file.variables.get(name) {
+        Some(variable) => variable.type_.clone(),
+        None => {
+            diagnostics.push(Diagnostic::error(codes::UNKNOWN_VARIABLE, format!("cannot find variable `{}`", name), span));
+            DataType::Unknown
+        }
+    }
+}
@@ -7,23 +7,25 @@ use crate::scope_placement_info::ScopePlacementInfo;
 
 
 
-pub fn find_var_type_from_local_scope<'compilation_unit>(var_name: &str, scope_placement_info: &'compilation_unit ScopePlacementInfo<'compilation_unit>) -> Option<DataType> {
-    let function = scope_placement_info.function_def;
-    let mut i = scope_placement_info.index;
-    loop {
-        match &function.body[i] {
-            ValidInFunctionBody::Variable(variable) => {
-                if variable.name == var_name {
-                    return Some(variable.type_.clone());
-                }
+pub fn find_var_type_from_local_scope(var_name: &str, scope_placement_info: &ScopePlacementInfo<'_>) -> Option<DataType> {
+    let mut scope = Some(scope_placement_info);
+    // walk outwards through the enclosing blocks, only looking at variables
+    // declared before the statement we are in
+    while let Some(current) = scope {
+        for statement in current.body[..=current.index].iter().rev() {
+            if let ValidInFunctionBody::Variable(variable) = statement
+                && variable.name == var_name
+            {
+                return Some(variable.type_.clone());
             }
-            _ => {}
         }
-        if i == 0 {
-            break;
+        if let Some((name, type_)) = &current.loop_variable
+            && *name == var_name
+        {
+            return Some(type_.clone());
         }
-        i -= 1;
-    } 
+        scope = current.parent;
+    }
     //as of now there is an unwritten assumption that no 2 params have the same name
     for param in scope_placement_info.function_def.args.iter() {
         if param.name == var_name {
@@ -31,4 +33,4 @@ pub fn find_var_type_from_local_scope<'compilation_unit>(var_name: &str, scope_p
         }
     }
     None
-}
\ No newline at end of file
+}
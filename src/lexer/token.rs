@@ -1,14 +1,26 @@
 use crate::ast::{AstComparable, ComparisonError};
+use crate::span::Span;
 use std::fmt;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     Identifier,
+    /// An int literal.
     Number,
+    /// A float literal, one with a fraction or an exponent.
+    Float,
     String,
+    /// A string with `{...}` interpolations in it.
+    Template,
+    /// `true` or `false`.
+    Bool,
     Punctuation,
     Operator,
     Keyword,
+    /// A `///` comment. Its value is the text after the slashes.
+    DocComment,
+    /// A `;`, or a line break that ends a statement. Its value is `;` or `\n`.
+    Terminator,
 }
 
 impl fmt::Display for TokenType {
@@ -16,10 +28,15 @@ impl fmt::Display for TokenType {
         match self {
             TokenType::Identifier => write!(f, "identifier"),
             TokenType::Number => write!(f, "number"),
+            TokenType::Float => write!(f, "float"),
             TokenType::String => write!(f, "string"),
+            TokenType::Template => write!(f, "template string"),
+            TokenType::Bool => write!(f, "bool"),
             TokenType::Punctuation => write!(f, "punctuation"),
             TokenType::Operator => write!(f, "operator"),
             TokenType::Keyword => write!(f, "keyword"),
+            TokenType::DocComment => write!(f, "doc comment"),
+            TokenType::Terminator => write!(f, "end of statement"),
         }
     }
 }
@@ -69,8 +86,57 @@ impl AstComparable for TokenType {
     }
 }
 
-#[derive(Debug)]
+/// The value of a literal, worked out by the lexer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    Float(f64),
+    /// The pieces of an interpolated string, in order.
+    Template(Vec<TemplatePart>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplatePart {
+    /// Text with its escapes already decoded.
+    Text(String),
+    /// The source of an embedded expression, without its braces.
+    Code(Span),
+}
+
+/// A comment the parser skips but a formatter or doc generator wants back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    /// The comment exactly as written, including its `//` or `/* */`.
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     pub type_: TokenType,
+    /// The token's text, without the quotes for strings.
     pub value: String,
+    pub span: Span,
+    /// The parsed value of a well-formed number literal or template string.
+    pub literal: Option<Literal>,
+    /// Comments between the previous token and this one.
+    pub trivia: Vec<Trivia>,
+}
+
+impl Token {
+    pub fn new(type_: TokenType, value: impl Into<String>, span: Span) -> Token {
+        Token { type_, value: value.into(), span, literal: None, trivia: Vec::new() }
+    }
+
+    pub fn with_literal(mut self, literal: Option<Literal>) -> Token {
+        self.literal = literal;
+        self
+    }
 }
@@ -1,9 +1,27 @@
-use super::token::{Token, TokenType};
+use super::token::{Literal, TemplatePart, Token, TokenType, Trivia, TriviaKind};
+use crate::diagnostics::{codes, Diagnostic, Diagnostics};
+use crate::span::Span;
+use crate::trace::{TraceEvent, TraceHook};
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-static OPERATOR_CHARS: &str = "+-*/|=<>";
-static PUNCTUATION_CHARS: &str = "()[]{},:";
+/// Every operator the lexer knows, longest first so that matching the first
+/// entry that fits gives the longest possible operator (maximal munch).
+static OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "%=",
+    "+", "-", "*", "/", "%", "=", "<", ">", "!", "|",
+];
+static PUNCTUATION_CHARS: &str = "()[]{},:.";
+
+/// How many bytes of source each entry of `Tokenizer::block_chars` covers.
+const CHAR_BLOCK: usize = 256;
+
+/// How many characters start in `bytes`, which is every byte that is not a
+/// UTF-8 continuation byte.
+fn count_chars(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&byte| byte & 0xC0 != 0x80).count()
+}
 
 static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
     let mut hm = HashMap::new();
@@ -13,207 +31,930 @@ static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
     hm.insert("else", TokenType::Keyword);
     hm.insert("while", TokenType::Keyword);
     hm.insert("for", TokenType::Keyword);
+    hm.insert("in", TokenType::Keyword);
     hm.insert("break", TokenType::Keyword);
     hm.insert("continue", TokenType::Keyword);
     hm.insert("func", TokenType::Keyword);
+    hm.insert("infix", TokenType::Keyword);
     hm.insert("struct", TokenType::Keyword);
+    hm.insert("true", TokenType::Bool);
+    hm.insert("false", TokenType::Bool);
     hm
 });
 
+/// Lexes a source on demand into a buffer of tokens, which the parser moves
+/// through and backtracks over by token position, so no text is lexed twice.
 pub struct Tokenizer<'a> {
     pub source: &'a str,
-    pub index: usize,
+    /// Byte offset of the next character to lex.
+    index: usize,
+    /// End of the last token lexed, where the comments before the next one start.
+    trivia_start: usize,
+    /// Every token lexed so far, terminators included.
+    tokens: Vec<Token>,
+    /// Position in `tokens` of the next token to hand out.
+    position: usize,
+    /// Whether the last token lexed could end a statement, which makes a line
+    /// break after it a terminator.
+    can_terminate: bool,
+    /// End of the last token handed out by `next_in_statement`, used to close node spans.
+    pub prev_end: usize,
+    /// Where the lexer, and the parser driving it, report problems.
+    pub diagnostics: Diagnostics,
+    /// Shared with sub-tokenizers so they report the same lines and columns.
+    line_starts: Rc<[usize]>,
+    /// How many characters come before each `CHAR_BLOCK` bytes of the source,
+    /// so a column is found without counting from the start of a long line.
+    block_chars: Rc<[usize]>,
+    /// Set for sub-tokenizers, whose source ends at a `}` rather than the end of the file.
+    embedded: bool,
+    /// Called with every trace event, if set.
+    trace: Option<TraceHook>,
+    /// Set when lexing a single line. Strings and comments reaching the end
+    /// are then left open for the next line instead of being errors.
+    line_mode: bool,
+    /// In line mode, what was still open when the source ran out.
+    open: Vec<OpenConstruct>,
+}
+
+/// A construct a line can end inside of, and so the next line start in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpenConstruct {
+    /// A string literal. `raw_hashes` is the number of `#` a raw string was
+    /// opened with, and `None` for an ordinary string.
+    String { raw_hashes: Option<usize> },
+    /// The code of a string interpolation, with `braces` unclosed `{` of its own.
+    Interpolation { braces: usize },
+    /// A block comment. Each level of nesting is an entry of its own.
+    BlockComment,
+}
+
+/// The lexer state at the start of a line: everything left open by the lines
+/// before it, outermost first. Plain code has nothing open, which is the
+/// default, so re-highlighting can stop at the first line whose start state
+/// comes out the same as before.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LineState {
+    open: Vec<OpenConstruct>,
+}
+
+impl LineState {
+    pub fn new(open: Vec<OpenConstruct>) -> LineState {
+        LineState { open }
+    }
+
+    pub fn open(&self) -> &[OpenConstruct] {
+        &self.open
+    }
+
+    pub fn is_code(&self) -> bool {
+        self.open.is_empty()
+    }
+}
+
+/// One line, lexed on its own by [`Tokenizer::tokenize_line`].
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// Spans count from the start of the line. A string carried over from the
+    /// line before is a token running from the start of the line to its
+    /// closing quote.
+    pub tokens: Vec<Token>,
+    /// Every comment outside strings, including the end of one carried over
+    /// from the line before.
+    pub comments: Vec<Trivia>,
+    /// The state the next line starts in.
+    pub end_state: LineState,
+}
+
+/// Everything needed to rewind the tokenizer to an earlier position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Checkpoint {
+    position: usize,
+    prev_end: usize,
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(source: &'a str) -> Tokenizer<'a> {
-        Tokenizer { source, index: 0 }
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let block_chars: Vec<usize> = std::iter::once(0)
+            .chain(source.as_bytes().chunks(CHAR_BLOCK).scan(0, |count, block| {
+                *count += count_chars(block);
+                Some(*count)
+            }))
+            .collect();
+        Tokenizer {
+            source,
+            index: 0,
+            trivia_start: 0,
+            tokens: Vec::new(),
+            position: 0,
+            can_terminate: false,
+            prev_end: 0,
+            diagnostics: Diagnostics::new(),
+            line_starts: Rc::from(line_starts),
+            block_chars: Rc::from(block_chars),
+            embedded: false,
+            trace: None,
+            line_mode: false,
+            open: Vec::new(),
+        }
+    }
+
+    /// A tokenizer for just `start..end` of this one's source, such as the code
+    /// inside a string interpolation. Its spans are positions in the whole source.
+    pub fn sub_tokenizer(&self, start: usize, end: usize) -> Tokenizer<'a> {
+        Tokenizer {
+            source: &self.source[..end],
+            index: start,
+            trivia_start: start,
+            tokens: Vec::new(),
+            position: 0,
+            can_terminate: false,
+            prev_end: start,
+            diagnostics: Diagnostics::new(),
+            line_starts: Rc::clone(&self.line_starts),
+            block_chars: Rc::clone(&self.block_chars),
+            embedded: true,
+            trace: self.trace.clone(),
+            line_mode: self.line_mode,
+            open: Vec::new(),
+        }
+    }
+
+    /// Lexes `line`, given without its line break, as if it followed lines
+    /// that left `state` open. An editor can re-lex just the line that
+    /// changed, and carry on down the file only while the end state differs
+    /// from the start state it had recorded for the next line.
+    pub fn tokenize_line(line: &str, state: &LineState) -> Line {
+        let mut tokenizer = Tokenizer::new(line);
+        tokenizer.line_mode = true;
+        let mut tokens = Vec::new();
+        let mut comments = Vec::new();
+        let mut open = state.open.clone();
+        // finish the innermost construct first, then the ones around it
+        while let Some(construct) = open.pop() {
+            let start = tokenizer.index;
+            let left_open = match construct {
+                OpenConstruct::BlockComment => {
+                    let mut depth = 1;
+                    while open.last() == Some(&OpenConstruct::BlockComment) {
+                        open.pop();
+                        depth += 1;
+                    }
+                    let (end, unclosed) = tokenizer.block_comment_end(start, depth);
+                    tokenizer.skip_to(end);
+                    comments.push(Trivia {
+                        kind: TriviaKind::BlockComment,
+                        text: line[start..end].to_string(),
+                        span: tokenizer.span(start, end),
+                    });
+                    vec![OpenConstruct::BlockComment; unclosed]
+                }
+                OpenConstruct::String { raw_hashes } => {
+                    let token = match raw_hashes {
+                        Some(hashes) => {
+                            let value = tokenizer.raw_string_rest(start, hashes);
+                            Token::new(TokenType::String, value, tokenizer.span(start, tokenizer.index))
+                        }
+                        None => tokenizer.string_rest(start, start),
+                    };
+                    tokenizer.skip_to(tokenizer.index);
+                    tokens.push(token);
+                    std::mem::take(&mut tokenizer.open)
+                }
+                OpenConstruct::Interpolation { mut braces } => {
+                    let mut closed = false;
+                    while let Some(token) = tokenizer.next_in_statement() {
+                        let is_brace = |brace: &str| token.type_ == TokenType::Punctuation && token.value == brace;
+                        if is_brace("{") {
+                            braces += 1;
+                        } else if is_brace("}") && braces == 0 {
+                            closed = true;
+                        } else if is_brace("}") {
+                            braces -= 1;
+                        }
+                        tokens.push(token);
+                        if closed {
+                            break;
+                        }
+                    }
+                    if closed {
+                        Vec::new()
+                    } else {
+                        std::iter::once(OpenConstruct::Interpolation { braces }).chain(tokenizer.open.drain(..)).collect()
+                    }
+                }
+            };
+            if !left_open.is_empty() {
+                open.extend(left_open);
+                return tokenizer.finish_line(tokens, comments, open);
+            }
+        }
+        while let Some(token) = tokenizer.next_in_statement() {
+            tokens.push(token);
+        }
+        let open = std::mem::take(&mut tokenizer.open);
+        tokenizer.finish_line(tokens, comments, open)
+    }
+
+    /// Carries on lexing at `offset`, with nothing before it left as trivia.
+    /// Only valid while every token lexed so far has been handed out.
+    fn skip_to(&mut self, offset: usize) {
+        self.index = offset;
+        self.trivia_start = offset;
+        self.prev_end = offset;
+    }
+
+    fn finish_line(&self, tokens: Vec<Token>, mut comments: Vec<Trivia>, open: Vec<OpenConstruct>) -> Line {
+        comments.extend(tokens.iter().flat_map(|token| token.trivia.iter().cloned()));
+        comments.extend(self.trivia_between(self.prev_end, self.source.len()));
+        Line { tokens, comments, end_state: LineState { open } }
+    }
+
+    /// The state each line of `source` starts in, as [`Tokenizer::tokenize_line`]
+    /// sees it. Lines end at `\n` or `\r\n`.
+    pub fn line_states(source: &str) -> Vec<LineState> {
+        let mut states = vec![LineState::default()];
+        for line in source.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)) {
+            let state = Tokenizer::tokenize_line(line, states.last().unwrap()).end_state;
+            states.push(state);
+        }
+        states.pop();
+        states
+    }
+
+    /// Starts passing trace events to `hook`.
+    pub fn set_trace(&mut self, hook: impl Fn(&TraceEvent) + 'static) {
+        self.trace = Some(Rc::new(hook));
+    }
+
+    pub fn trace(&self, event: TraceEvent) {
+        if let Some(hook) = &self.trace {
+            hook(&event);
+        }
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { position: self.position, prev_end: self.prev_end }
+    }
+
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        if checkpoint.position != self.position {
+            self.trace(TraceEvent::Backtrack { from: self.position, to: checkpoint.position });
+        }
+        self.position = checkpoint.position;
+        self.prev_end = checkpoint.prev_end;
+    }
+
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let (line, col) = self.get_line_and_col_from_pos(start);
+        Span::new(start, end, line, col)
+    }
+
+    /// The span from `start` up to the end of the last consumed token.
+    pub fn span_from(&self, start: usize) -> Span {
+        self.span(start, self.prev_end.max(start))
+    }
+
+    /// Where the next token, or terminator, begins.
+    pub fn next_token_start(&mut self) -> usize {
+        let end = self.source.len();
+        self.peek_raw(0).map_or(end, |token| token.span.start)
     }
 
+    /// The token `n` places past the next one, lexing up to it if needed.
+    /// Terminators count as tokens here.
+    fn peek_raw(&mut self, n: usize) -> Option<&Token> {
+        while self.tokens.len() <= self.position + n {
+            let token = self.lex_token()?;
+            self.tokens.push(token);
+        }
+        self.tokens.get(self.position + n)
+    }
+
+    /// The character at the current byte position. Only call this while
+    /// `in_source()`.
     fn cur_char(&self) -> char {
-        self.source.chars().nth(self.index).unwrap()
+        self.source[self.index..].chars().next().unwrap_or('\0')
     }
+
+    fn is_identifier_start(c: char) -> bool {
+        c == '_' || unicode_ident::is_xid_start(c)
+    }
+
+    /// Reads an identifier or keyword: an XID start character or `_`
+    /// followed by any number of XID continue characters.
     fn next_word(&mut self) -> String {
         let start = self.index;
-        while self.in_range()
-            && ((self.cur_char() >= 'a' && self.cur_char() <= 'z')
-                || (self.cur_char() >= 'A' && self.cur_char() <= 'Z')
-                || self.cur_char() == '_')
-        {
-            self.index += 1;
-        }
+        let length = self.source[start..]
+            .char_indices()
+            .find(|&(i, c)| !(if i == 0 { Tokenizer::is_identifier_start(c) } else { unicode_ident::is_xid_continue(c) }))
+            .map_or(self.source.len() - start, |(i, _)| i);
+        self.index += length;
         self.source[start..self.index].to_string()
     }
 
-    fn next_number(&mut self) -> String {
-        let start = self.index;
-        while self.in_range() && (self.cur_char().is_numeric() || self.cur_char() == '.') {
+    /// The byte `offset` bytes past the current position, if there is one.
+    fn byte_at(&self, offset: usize) -> Option<u8> {
+        self.source.as_bytes().get(self.index + offset).copied()
+    }
+
+    /// Skips digits of the given radix and `_` separators.
+    fn eat_digits(&mut self, radix: u32) {
+        while self.byte_at(0).is_some_and(|b| b == b'_' || (b as char).is_digit(radix)) {
             self.index += 1;
         }
-        self.source[start..self.index].to_string()
     }
 
-    fn next_string(&mut self) -> String {
-        self.index += 1;
+    /// Reads an int literal, or a float literal if it has a fraction or an
+    /// exponent. Ints may be written in hex, binary or octal with a `0x`, `0b`
+    /// or `0o` prefix, and any literal may use `_` between two digits.
+    /// Anything glued on after a valid literal, like the `.3` of `1.2.3` or
+    /// the `e` of `1e`, or a misplaced `_`, makes it malformed: the whole run
+    /// is consumed and reported so lexing can carry on after it.
+    fn next_number(&mut self) -> Token {
         let start = self.index;
-        while self.cur_char() != '"' {
-            self.index += 1;
+        let radix = match (self.byte_at(0), self.byte_at(1)) {
+            (Some(b'0'), Some(b'x' | b'X')) => 16,
+            (Some(b'0'), Some(b'b' | b'B')) => 2,
+            (Some(b'0'), Some(b'o' | b'O')) => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.index += 2;
+        }
+        let digits_start = self.index;
+        let mut type_ = TokenType::Number;
+        self.eat_digits(radix);
+        if radix == 10 {
+            if self.byte_at(0) == Some(b'.') && self.byte_at(1).is_some_and(|b| b.is_ascii_digit()) {
+                self.index += 1;
+                self.eat_digits(10);
+                type_ = TokenType::Float;
+            }
+            if matches!(self.byte_at(0), Some(b'e' | b'E')) {
+                let sign = usize::from(matches!(self.byte_at(1), Some(b'+' | b'-')));
+                if self.byte_at(1 + sign).is_some_and(|b| b.is_ascii_digit()) {
+                    self.index += 1 + sign;
+                    self.eat_digits(10);
+                    type_ = TokenType::Float;
+                }
+            }
+        }
+        let written = &self.source[digits_start..self.index];
+        let digits = written.replace('_', "");
+        // a `_` must sit between two digits, so not doubled, trailing or
+        // straight after a radix prefix
+        let is_digit = |b: Option<&u8>| b.is_some_and(|&b| (b as char).is_digit(radix));
+        let bytes = written.as_bytes();
+        let misplaced_separator = (0..bytes.len())
+            .any(|i| bytes[i] == b'_' && !(i > 0 && is_digit(bytes.get(i - 1)) && is_digit(bytes.get(i + 1))));
+        let glued = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+        if digits.is_empty()
+            || misplaced_separator
+            || self.byte_at(0).is_some_and(glued)
+            || (self.byte_at(0) == Some(b'.') && self.byte_at(1).is_some_and(|b| b.is_ascii_digit()))
+        {
+            while self.byte_at(0).is_some_and(|b| glued(b) || b == b'.') {
+                self.index += 1;
+            }
+            let mut diagnostic = Diagnostic::error(
+                codes::MALFORMED_NUMBER,
+                format!("malformed number literal `{}`", &self.source[start..self.index]),
+                self.span(start, self.index),
+            );
+            if misplaced_separator {
+                diagnostic = diagnostic.with_note("`_` may only separate two digits");
+            }
+            self.diagnostics.push(diagnostic);
+            return Token::new(type_, &self.source[start..self.index], self.span(start, self.index));
         }
-        let end = self.index;
+        let text = &self.source[start..self.index];
+        let span = self.span(start, self.index);
+        let literal = match type_ {
+            TokenType::Float => match digits.parse::<f64>() {
+                Ok(value) if value.is_finite() => Some(Literal::Float(value)),
+                _ => {
+                    self.diagnostics.push(
+                        Diagnostic::error(codes::NUMBER_OVERFLOW, format!("float literal `{}` is too large for float", text), span)
+                            .with_note(format!("float holds values up to {:e}", f64::MAX)),
+                    );
+                    None
+                }
+            },
+            _ => match i64::from_str_radix(&digits, radix) {
+                Ok(value) => Some(Literal::Int(value)),
+                Err(_) => {
+                    self.diagnostics.push(
+                        Diagnostic::error(codes::NUMBER_OVERFLOW, format!("integer literal `{}` is too large for int", text), span)
+                            .with_note(format!("int holds values up to {}", i64::MAX)),
+                    );
+                    None
+                }
+            },
+        };
+        Token::new(type_, text, span).with_literal(literal)
+    }
+
+    /// Reads a string literal from its opening quote up to and including the
+    /// closing one, decoding escape sequences. Strings may span lines.
+    ///
+    /// `{expression}` inside a string interpolates, which makes the token a
+    /// template whose parts are the text around the braces and the spans of
+    /// the code inside them. `{{` and `}}` stand for literal braces.
+    fn next_string(&mut self) -> Token {
+        let start = self.index;
         self.index += 1;
-        self.source[start..end].to_string()
+        self.string_rest(start, self.index)
     }
-    pub fn in_range(&self) -> bool {
-        self.index < self.source.len()
+
+    /// Reads a string from `body_start`, just past its opening quote or the
+    /// start of a line it was carried onto, to its end.
+    fn string_rest(&mut self, start: usize, body_start: usize) -> Token {
+        let mut value = String::new();
+        let mut parts = Vec::new();
+        let mut text_end = self.source.len();
+        loop {
+            let Some(c) = self.source[self.index..].chars().next() else {
+                self.unterminated_string(start, body_start, None);
+                break;
+            };
+            self.index += c.len_utf8();
+            match c {
+                '"' => {
+                    text_end = self.index - 1;
+                    break;
+                }
+                '\\' => {
+                    if let Some(decoded) = self.next_escape(self.index - 1) {
+                        value.push(decoded);
+                    }
+                }
+                '{' | '}' if self.byte_at(0) == Some(c as u8) => {
+                    self.index += 1;
+                    value.push(c);
+                }
+                '{' => match self.interpolation_end() {
+                    Ok(end) => {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut value)));
+                        parts.push(TemplatePart::Code(self.span(self.index, end)));
+                        self.index = end + 1;
+                    }
+                    Err(open) if !open.is_empty() => {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut value)));
+                        parts.push(TemplatePart::Code(self.span(self.index, self.source.len())));
+                        self.index = self.source.len();
+                        self.open = std::iter::once(OpenConstruct::String { raw_hashes: None }).chain(open).collect();
+                        break;
+                    }
+                    Err(_) => {
+                        self.diagnostics.push(
+                            Diagnostic::error(codes::UNTERMINATED_INTERPOLATION, "unterminated interpolation", self.span(self.index - 1, self.index))
+                                .with_note("close it with `}` on the same line, or write `{{` for a literal brace"),
+                        );
+                        value.push(c);
+                    }
+                },
+                _ => value.push(c),
+            }
+        }
+        let span = self.span(start, self.index);
+        if parts.is_empty() {
+            return Token::new(TokenType::String, value, span);
+        }
+        parts.push(TemplatePart::Text(value));
+        Token::new(TokenType::Template, &self.source[body_start..text_end], span).with_literal(Some(Literal::Template(parts)))
     }
-    pub fn eat_lines(&mut self) {
-        while self.in_range() && (self.cur_char() == ' ' || self.cur_char() == '\t' || self.cur_char() == '\n') {
-            self.index += 1;
+
+    /// Where the `}` closing an interpolation that starts at the current
+    /// position is. The code inside is lexed to find it, so braces in nested
+    /// strings do not count. If the line ends first the error holds what was
+    /// left open, which is nothing unless a single line is being lexed and it
+    /// ends inside a string or comment within the interpolation.
+    fn interpolation_end(&self) -> Result<usize, Vec<OpenConstruct>> {
+        let mut scanner = self.sub_tokenizer(self.index, self.source.len());
+        let mut depth = 0;
+        while let Some(token) = scanner.next_in_statement() {
+            if token.type_ != TokenType::Punctuation {
+                continue;
+            }
+            match token.value.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(token.span.start),
+                "}" => depth -= 1,
+                _ => {}
+            }
+        }
+        if scanner.open.is_empty() {
+            return Err(Vec::new());
         }
+        Err(std::iter::once(OpenConstruct::Interpolation { braces: depth }).chain(scanner.open).collect())
     }
-    fn eat_spaces(&mut self) {
-        while self.in_range() && (self.cur_char() == ' ' || self.cur_char() == '\t') {
-            self.index += 1;
+
+    /// Decodes the escape sequence whose backslash is at `start`. Bad escapes
+    /// are reported and decode to nothing.
+    fn next_escape(&mut self, start: usize) -> Option<char> {
+        // at the end of the source the string itself is reported as unterminated
+        let c = self.source[self.index..].chars().next()?;
+        self.index += c.len_utf8();
+        match c {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            'u' => self.next_unicode_escape(start),
+            _ => {
+                self.diagnostics.push(
+                    Diagnostic::error(codes::INVALID_ESCAPE, format!("unknown escape sequence `\\{}`", c), self.span(start, self.index))
+                        .with_note("supported escapes are \\n, \\t, \\\\, \\\" and \\u{...}"),
+                );
+                None
+            }
         }
     }
 
-    pub fn peek(&mut self) -> Option<Token> {
-        let index = self.index;
-        let token = self.next();
-        self.index = index;
-        token
+    /// Decodes the rest of a `\u{...}` escape: one to six hex digits naming a
+    /// Unicode scalar value.
+    fn next_unicode_escape(&mut self, start: usize) -> Option<char> {
+        let mut decoded = None;
+        if self.byte_at(0) == Some(b'{') {
+            self.index += 1;
+            let digits_start = self.index;
+            while self.byte_at(0).is_some_and(|b| b.is_ascii_hexdigit()) {
+                self.index += 1;
+            }
+            let digits = &self.source[digits_start..self.index];
+            if self.byte_at(0) == Some(b'}') {
+                self.index += 1;
+                if (1..=6).contains(&digits.len()) {
+                    decoded = u32::from_str_radix(digits, 16).ok().and_then(char::from_u32);
+                }
+            }
+        }
+        if decoded.is_none() {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    codes::INVALID_ESCAPE,
+                    format!("invalid unicode escape `{}`", &self.source[start..self.index]),
+                    self.span(start, self.index),
+                )
+                .with_note("write unicode escapes as \\u{...} with one to six hex digits"),
+            );
+        }
+        decoded
     }
-    pub fn next(&mut self) -> Option<Token> {
-        let next_token = self.private_next();
-        // println!("just tokenized {:?}", next_token);
-        next_token
+
+    /// Whether a raw string, `r"..."` or `r#"..."#` with any number of `#`,
+    /// starts at the current position.
+    fn at_raw_string(&self) -> bool {
+        let rest = &self.source.as_bytes()[self.index..];
+        let hashes = rest.iter().skip(1).take_while(|&&b| b == b'#').count();
+        rest.first() == Some(&b'r') && rest.get(1 + hashes) == Some(&b'"')
     }
 
-    fn next_operator(&mut self) -> String {
+    /// Reads a raw string. Nothing inside is an escape, and it only ends at a
+    /// quote followed by as many `#` as it was opened with.
+    fn next_raw_string(&mut self) -> String {
         let start = self.index;
-        while self.in_range() && OPERATOR_CHARS.contains(self.cur_char()) {
-            self.index += 1;
+        let hashes = self.source[start + 1..].bytes().take_while(|&b| b == b'#').count();
+        self.index += hashes + 2;
+        self.raw_string_rest(start, hashes)
+    }
+
+    /// Reads a raw string opened with `hashes` `#` from the current position
+    /// to its end.
+    fn raw_string_rest(&mut self, start: usize, hashes: usize) -> String {
+        let closing = format!("\"{}", "#".repeat(hashes));
+        match self.source[self.index..].find(&closing) {
+            Some(length) => {
+                let value = self.source[self.index..self.index + length].to_string();
+                self.index += length + closing.len();
+                value
+            }
+            None => {
+                let value = self.source[self.index..].to_string();
+                let opening_end = self.index;
+                self.index = self.source.len();
+                self.unterminated_string(start, opening_end, Some(hashes));
+                value
+            }
         }
-        let end = self.index;
-        self.source[start..end].to_string()
     }
 
-    fn private_next(&mut self) -> Option<Token> {
-        println!(
-            "[tokenizer] next_under_wapper() called, current index: {}",
-            self.index
+    /// Reports a string running to the end of the source, or in line mode
+    /// leaves it open for the next line.
+    fn unterminated_string(&mut self, start: usize, opening_end: usize, raw_hashes: Option<usize>) {
+        if self.line_mode {
+            self.open = vec![OpenConstruct::String { raw_hashes }];
+            return;
+        }
+        self.diagnostics.push(
+            Diagnostic::error(codes::UNTERMINATED_STRING, "unterminated string literal", self.span(start, opening_end))
+                .with_note("the string runs to the end of the file without a closing quote"),
         );
-        self.eat_spaces();
+    }
 
-        if !self.in_range() {
-            println!("[tokenizer] No more characters in range, returning None");
-            return None;
+    /// Whether any tokens are left, on this line or a later one.
+    pub fn in_range(&mut self) -> bool {
+        self.peek_raw(0).is_some()
+    }
+
+    /// Whether any source is left to lex.
+    fn in_source(&self) -> bool {
+        self.index < self.source.len()
+    }
+
+    /// Skips terminators: line breaks and `;`.
+    pub fn eat_lines(&mut self) {
+        while self.skip_stop() {}
+    }
+
+    /// Skips spaces, tabs and comments, stopping at a line break. A block
+    /// comment is skipped whole even if it spans several lines.
+    fn eat_spaces(&mut self) {
+        loop {
+            match (self.byte_at(0), self.byte_at(1)) {
+                // a `\r` is only whitespace, so `\r\n` line breaks work like `\n`
+                (Some(b' ' | b'\t' | b'\r'), _) => self.index += 1,
+                (Some(b'/'), Some(b'/')) if !self.at_doc_comment() => self.index = self.line_comment_end(self.index),
+                // a doc comment trailing code has nothing below it to document
+                (Some(b'/'), Some(b'/')) if self.after_code_on_line() => {
+                    let start = self.index;
+                    self.index = self.line_comment_end(start);
+                    self.diagnostics.push(
+                        Diagnostic::warning(codes::UNUSED_DOC_COMMENT, "unused doc comment", self.span(start, self.index))
+                            .with_note("doc comments go on the lines above what they document; use `//` for a comment after code"),
+                    );
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    let start = self.index;
+                    let (end, unclosed) = self.block_comment_end(start, 0);
+                    self.index = end;
+                    if unclosed > 0 && self.line_mode {
+                        self.open = vec![OpenConstruct::BlockComment; unclosed];
+                    } else if unclosed > 0 {
+                        self.diagnostics.push(
+                            Diagnostic::error(codes::UNTERMINATED_COMMENT, "unterminated block comment", self.span(start, start + 2))
+                                .with_note("block comments nest, so every `/*` needs its own `*/`"),
+                        );
+                    }
+                }
+                _ => return,
+            }
         }
+    }
 
-        let current_char = self.cur_char();
-        println!(
-            "[tokenizer] Current character: '{}' (index: {})",
-            current_char, self.index
-        );
+    /// Whether a `///` doc comment starts at the current position. Like in
+    /// Rust, four or more slashes make an ordinary comment again.
+    fn at_doc_comment(&self) -> bool {
+        let rest = &self.source[self.index..];
+        rest.starts_with("///") && !rest.starts_with("////")
+    }
 
-        if current_char.is_alphabetic() {
-            println!("[tokenizer] Found alphabetic character, reading word...");
-            let word = self.next_word();
-            println!("[tokenizer] Read word: '{}'", word);
-
-            if let Some(keyword) = KEYWORDS.get(word.as_str()) {
-                let token = Token {
-                    type_: keyword.clone(),
-                    value: word,
-                };
-                println!("[tokenizer] Matched keyword: {:?}", token);
-                return Some(token);
+    /// Whether a token other than a line break was lexed earlier on the line
+    /// the lexer is on.
+    fn after_code_on_line(&self) -> bool {
+        self.tokens.last().is_some_and(|last| last.type_ != TokenType::Terminator && !self.source[last.span.end..self.index].contains('\n'))
+    }
+
+    /// Where the line comment starting at `start` ends: the next line break,
+    /// which is not part of it.
+    fn line_comment_end(&self, start: usize) -> usize {
+        let rest = &self.source[start..];
+        let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+        start + line.trim_end_matches('\r').len()
+    }
+
+    /// Where the block comment at `start`, already `depth` levels deep, ends:
+    /// just past its `*/`, or at the end of the source with the number of
+    /// levels still unclosed. Block comments nest.
+    fn block_comment_end(&self, start: usize, depth: usize) -> (usize, usize) {
+        let bytes = self.source.as_bytes();
+        let mut depth = depth;
+        let mut i = start;
+        while i + 1 < bytes.len() {
+            match (bytes[i], bytes[i + 1]) {
+                (b'/', b'*') => {
+                    depth += 1;
+                    i += 2;
+                }
+                (b'*', b'/') => {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        return (i, 0);
+                    }
+                }
+                _ => i += 1,
             }
+        }
+        (bytes.len(), depth)
+    }
 
-            let token = Token {
-                type_: TokenType::Identifier,
-                value: word,
+    /// The comments in `start..end`, a stretch the lexer skipped between two
+    /// tokens.
+    fn trivia_between(&self, start: usize, end: usize) -> Vec<Trivia> {
+        let mut trivia = Vec::new();
+        let mut i = start;
+        while i < end {
+            let rest = &self.source[i..end];
+            let (kind, comment_end) = if rest.starts_with("//") {
+                (TriviaKind::LineComment, self.line_comment_end(i))
+            } else if rest.starts_with("/*") {
+                (TriviaKind::BlockComment, self.block_comment_end(i, 0).0)
+            } else {
+                i += rest.chars().next().map_or(1, char::len_utf8);
+                continue;
             };
-            println!("[tokenizer] Created identifier token: {:?}", token);
-            return Some(token);
+            trivia.push(Trivia {
+                kind,
+                text: self.source[i..comment_end].to_string(),
+                span: self.span(i, comment_end),
+            });
+            i = comment_end;
         }
+        trivia
+    }
 
-        if current_char.is_numeric() {
-            println!("[tokenizer] Found numeric character, reading number...");
-            let number = self.next_number();
-            let token = Token {
-                type_: TokenType::Number,
-                value: number,
-            };
-            println!("[tokenizer] Created number token: {:?}", token);
-            return Some(token);
+    /// Steps over whatever made `next_in_statement` stop without producing a token, which
+    /// is a terminator unless the source has run out. Returns whether it was
+    /// a terminator.
+    pub fn skip_stop(&mut self) -> bool {
+        match self.peek_raw(0) {
+            Some(token) if token.type_ == TokenType::Terminator => {
+                self.position += 1;
+                true
+            }
+            _ => false,
         }
+    }
 
-        if current_char == '"' {
-            println!("[tokenizer] Found string delimiter, reading string...");
-            let string_val = self.next_string();
-            let token = Token {
-                type_: TokenType::String,
-                value: string_val,
-            };
-            println!("[tokenizer] Created string token: {:?}", token);
-            return Some(token);
+    /// The token `next_in_statement` would hand out.
+    pub fn peek(&mut self) -> Option<Token> {
+        self.peek_nth(0).cloned()
+    }
+
+    /// The token `n` places past the one `next_in_statement` would hand out, if no
+    /// terminator comes before it.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        self.peek_raw(n)?;
+        let upcoming = &self.tokens[self.position..=self.position + n];
+        if upcoming.iter().any(|token| token.type_ == TokenType::Terminator) {
+            return None;
         }
+        upcoming.last()
+    }
 
-        if PUNCTUATION_CHARS.contains(current_char) {
-            let this_char = current_char;
-            println!("[tokenizer] Found punctuation: '{}'", this_char);
-            self.index += 1;
-            let token = Token {
-                type_: TokenType::Punctuation,
-                value: this_char.to_string(),
-            };
-            println!("[tokenizer] Created punctuation token: {:?}", token);
-            return Some(token);
+    /// Hands out the next token, or `None` at a terminator or the end of the
+    /// source. Terminators end statements, so stepping over them is left to
+    /// `skip_stop` and `eat_lines`.
+    pub fn next_in_statement(&mut self) -> Option<Token> {
+        let token = self.peek_nth(0)?.clone();
+        self.position += 1;
+        self.prev_end = token.span.end;
+        self.trace(TraceEvent::Token(&token));
+        Some(token)
+    }
+
+    /// Lexes the token at the current byte position, with the comments since
+    /// the last token as its trivia.
+    fn lex_token(&mut self) -> Option<Token> {
+        let mut token = self.lex_bare_token()?;
+        // a doc comment leaves the line break after it as it found it
+        if token.type_ != TokenType::DocComment {
+            self.can_terminate = Tokenizer::ends_statement(&token);
+        }
+        if token.type_ != TokenType::Terminator {
+            token.trivia = self.trivia_between(self.trivia_start, token.span.start);
+            self.trivia_start = token.span.end;
         }
+        Some(token)
+    }
 
-        if OPERATOR_CHARS.contains(current_char) {
-            let operator = self.next_operator();
-            let token = Token {
-                type_: TokenType::Operator,
-                value: operator,
-            };
-            println!("[tokenizer] Created operator token: {:?}", token);
-            return Some(token);
+    /// Whether a line break after `token` ends the statement, as in Go: after
+    /// a name, a literal, `break`, `continue`, `return` or a closing bracket.
+    /// After anything else, such as an operator or a comma, the statement
+    /// carries on on the next line.
+    fn ends_statement(token: &Token) -> bool {
+        match token.type_ {
+            TokenType::Identifier | TokenType::Number | TokenType::Float | TokenType::String | TokenType::Template | TokenType::Bool => true,
+            TokenType::Keyword => matches!(token.value.as_str(), "break" | "continue" | "return"),
+            TokenType::Punctuation => matches!(token.value.as_str(), ")" | "]" | "}"),
+            TokenType::Operator | TokenType::DocComment | TokenType::Terminator => false,
         }
+    }
 
-        match current_char {
-            ' ' => {
-                println!("[tokenizer] Found space, skipping and recursing...");
+    fn next_operator(&mut self) -> Option<&'static str> {
+        let rest = &self.source[self.index..];
+        let operator = OPERATORS.iter().find(|operator| rest.starts_with(*operator))?;
+        self.index += operator.len();
+        Some(operator)
+    }
+
+    fn lex_bare_token(&mut self) -> Option<Token> {
+        // unknown characters are reported and skipped, until a token turns up
+        loop {
+            self.eat_spaces();
+            // line breaks that do not end a statement are just whitespace
+            while !self.can_terminate && self.byte_at(0) == Some(b'\n') {
                 self.index += 1;
-                self.next()
+                self.eat_spaces();
             }
-            _ => {
-                println!(
-                    "[tokenizer] Unrecognized character: '{}' (index: {})",
-                    current_char, self.index
-                );
-                None
+
+            if !self.in_source() {
+                return None;
+            }
+
+            let start = self.index;
+            let current_char = self.cur_char();
+
+            if current_char == '\n' || current_char == ';' {
+                self.index += 1;
+                return Some(Token::new(TokenType::Terminator, current_char.to_string(), self.span(start, self.index)));
+            }
+
+            if self.at_doc_comment() {
+                self.index = self.line_comment_end(start);
+                let text = &self.source[start + 3..self.index];
+                let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+                return Some(Token::new(TokenType::DocComment, text, self.span(start, self.index)));
+            }
+
+            if self.at_raw_string() {
+                let string_val = self.next_raw_string();
+                return Some(Token::new(TokenType::String, string_val, self.span(start, self.index)));
+            }
+
+            if Tokenizer::is_identifier_start(current_char) {
+                let word = self.next_word();
+                if let Some(keyword) = KEYWORDS.get(word.as_str()) {
+                    return Some(Token::new(keyword.clone(), word, self.span(start, self.index)));
+                }
+
+                return Some(Token::new(TokenType::Identifier, word, self.span(start, self.index)));
+            }
+
+            if current_char.is_ascii_digit() {
+                return Some(self.next_number());
+            }
+
+            if current_char == '"' {
+                return Some(self.next_string());
+            }
+
+            if PUNCTUATION_CHARS.contains(current_char) {
+                self.index += 1;
+                return Some(Token::new(TokenType::Punctuation, current_char.to_string(), self.span(start, self.index)));
+            }
+
+            if let Some(operator) = self.next_operator() {
+                return Some(Token::new(TokenType::Operator, operator, self.span(start, self.index)));
             }
+
+            self.index += current_char.len_utf8();
+            let span = self.span(start, self.index);
+            self.trace(TraceEvent::UnknownCharacter { character: current_char, span });
+            self.diagnostics.push(Diagnostic::error(
+                codes::UNKNOWN_CHARACTER,
+                format!("unknown character `{}`", current_char.escape_debug()),
+                span,
+            ));
         }
     }
 
+    /// 1-based line and column of the byte offset `pos`. Columns count
+    /// characters, not bytes.
     pub fn get_line_and_col_from_pos(&self, pos: usize) -> (usize, usize) {
-        let line = self.source.lines().count();
-        let col = self.source.lines().nth(line - 1).unwrap().len();
-        (line, col)
-    }
-
-    pub fn show_user_error(&mut self, start_pos: usize, end_pos: usize, message: String) {
-        let all_text_up_until_this_point = &self.source[..start_pos];
-        print!("{}", all_text_up_until_this_point);
-        println!("{}", red(self.source[start_pos..end_pos].to_string()));
-        panic!(
-            "{} on line {} column {}",
-            red(message),
-            self.get_line_and_col_from_pos(start_pos).0,
-            self.get_line_and_col_from_pos(start_pos).1
-        );
+        let line_index = match self.line_starts.binary_search(&pos) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let col = self.chars_before(pos.min(self.source.len())) - self.chars_before(self.line_starts[line_index]);
+        (line_index + 1, col + 1)
+    }
+
+    /// How many characters the source has before the byte offset `pos`.
+    fn chars_before(&self, pos: usize) -> usize {
+        let block = pos / CHAR_BLOCK;
+        self.block_chars[block] + count_chars(&self.source.as_bytes()[block * CHAR_BLOCK..pos])
+    }
+
+    /// The error for running out of tokens while `expected` was wanted.
+    pub fn unexpected_end(&self, expected: &str) -> Box<Diagnostic> {
+        let stop = self.tokens.get(self.position);
+        let start = stop.map_or(self.source.len(), |token| token.span.start);
+        let span = self.span(start, start);
+        Box::new(if let Some(stop) = stop && stop.value == ";" {
+            Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected {}, found `;`", expected), span)
+        } else if stop.is_some() {
+            Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected {}, found end of line", expected), span)
+        } else if self.embedded {
+            Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected {}, found `}}`", expected), span)
+        } else {
+            Diagnostic::error(codes::UNEXPECTED_EOF, format!("expected {}, found end of file", expected), span)
+        })
     }
 
     pub fn optionally_expect_type(&mut self, type_: TokenType) -> bool {
@@ -225,114 +966,107 @@ impl<'a> Tokenizer<'a> {
         if token.type_ != type_ {
             return false;
         }
-        self.next();
+        self.next_in_statement();
         true
     }
-    pub fn expect(&mut self, type_: TokenType) -> Token {
-        let start_pos = self.index;
-        let token = self.next().unwrap();
+    /// Consumes the next token if it is of type `type_`. On a mismatch the
+    /// token is left in place for error recovery to look at.
+    pub fn expect(&mut self, type_: TokenType) -> Result<Token, Box<Diagnostic>> {
+        let checkpoint = self.checkpoint();
+        let token = self.next_in_statement().ok_or_else(|| self.unexpected_end(&type_.to_string()))?;
         if token.type_ != type_ {
-            self.show_user_error(
-                start_pos,
-                self.index,
-                format!("Expected {:?} got {:?}", type_, token.value),
-            );
+            self.restore(checkpoint);
+            return Err(Box::new(Diagnostic::error(
+                codes::UNEXPECTED_TOKEN,
+                format!("expected {}, found `{}`", type_, token.value),
+                token.span,
+            )));
         }
-        token
+        Ok(token)
     }
 
-    pub fn expect_punctuation(&mut self, value: char) -> Token {
-        let token = self.next().unwrap();
+    pub fn expect_punctuation(&mut self, value: char) -> Result<Token, Box<Diagnostic>> {
+        let checkpoint = self.checkpoint();
+        let token = self.next_in_statement().ok_or_else(|| self.unexpected_end(&format!("`{}`", value)))?;
         if token.type_ != TokenType::Punctuation
             || token.value.len() != 1
-            || token.value.chars().nth(0).unwrap() != value
+            || !token.value.starts_with(value)
         {
-            self.show_user_error(
-                self.index,
-                self.index,
-                format!("Expected {} got {}", value, token.value),
-            );
+            self.restore(checkpoint);
+            return Err(Box::new(Diagnostic::error(
+                codes::UNEXPECTED_TOKEN,
+                format!("expected `{}`, found `{}`", value, token.value),
+                token.span,
+            )));
         }
-        token
+        Ok(token)
     }
-    fn optionally_expect(&mut self, type_: TokenType) -> Option<String> {
-        let position_at_start = self.index;
-        let token = self.next();
-        if token.is_none() {
-            self.index = position_at_start;
-            return None;
-        }
-        let token = token.unwrap();
-        if token.type_ != type_ {
-            self.index = position_at_start;
-            return None;
-        }
-        Some(token.value)
-    }
-
     pub fn optionally_expect_string(&mut self, value: &str) -> bool {
         if !self.in_range() {
             return false;
         }
-        let position_at_start = self.index;
-        let token = self.next();
+        let position_at_start = self.checkpoint();
+        let token = self.next_in_statement();
         if token.is_none() {
-            self.index = position_at_start;
+            self.restore(position_at_start);
             return false;
         }
         let token = token.unwrap();
         if token.value != value {
-            self.index = position_at_start;
+            self.restore(position_at_start);
             return false;
         }
         true
     }
 
     pub fn optionally_expect_keyword_of(&mut self, value: &str) -> bool {
-        let position_at_start = self.index;
-        let token = self.next();
+        let position_at_start = self.checkpoint();
+        let token = self.next_in_statement();
         if token.is_none() {
-            self.index = position_at_start;
+            self.restore(position_at_start);
             return false;
         }
         let token = token.unwrap();
         if token.type_ != TokenType::Keyword {
-            self.index = position_at_start;
+            self.restore(position_at_start);
             return false;
         }
         if token.value == value {
             return true;
         }
-        self.index = position_at_start;
-        return false;
+        self.restore(position_at_start);
+        false
     }
 
     pub fn optionally_expect_punctuation(&mut self, value: char) -> bool {
-        let position_at_start = self.index;
-        let token = self.next();
+        let position_at_start = self.checkpoint();
+        let token = self.next_in_statement();
         if token.is_none() {
-            self.index = position_at_start;
+            self.restore(position_at_start);
             return false;
         }
         let token = token.unwrap();
         if token.type_ != TokenType::Punctuation {
-            self.index = position_at_start;
+            self.restore(position_at_start);
             return false;
         }
-        println!(
-            "in optionally_expect_punctuation: token.value.chars().nth(0).unwrap(): {}",
-            token.value.chars().nth(0).unwrap()
-        );
-        if token.value.chars().nth(0).unwrap() == value {
-            println!("returning true");
+        if token.value.starts_with(value) {
             return true;
         }
-        println!("returning false");
-        self.index = position_at_start;
-        return false;
+        self.restore(position_at_start);
+        false
     }
 }
 
-fn red(text: String) -> String {
-    format!("\x1B[31m{}\x1B[0m", text)
+/// Every remaining token through to the end of the source, terminators
+/// included.
+impl Iterator for Tokenizer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.skip_stop() {
+            return Some(self.tokens[self.position - 1].clone());
+        }
+        self.next_in_statement()
+    }
 }
@@ -1,7 +1,10 @@
 // Core compiler modules
 pub mod ast;
 pub mod data_type;
+pub mod diagnostics;
 pub mod parser;
+pub mod span;
+pub mod trace;
 
 // Lexer modules
 pub mod lexer {
@@ -1,18 +1,12 @@
-use colored::*;
-use compiler_11::{
-    lexer::token::TokenType,
-    parser::Parser,
-};
-
 mod in_function_scope_validation;
 mod scope_placement_info;
 mod validate_function_types;
 mod get_type;
 mod file;
 use crate::file::File;
+use compiler_11::diagnostics::Renderer;
 
 // Enable colored output
-use std::io::Write;
 use std::sync::Once;
 
 static INIT: Once = Once::new();
@@ -30,7 +24,7 @@ fn main() {
     // Example 1: Variable declaration
     let code = r#"
 
-        is_greater_than(a int, b int):int{}
+        infix func is_greater_than(a int, b int): int {}
 
         var n int = 1 is_greater_than 9
         var num int = 3 - n
@@ -53,7 +47,14 @@ fn main() {
     "#;
 
 
-    let file = File::parse(code);   
+    let file = match File::compile(code) {
+        Ok(file) => file,
+        Err(diagnostics) => {
+            eprintln!("{}", Renderer::new("main", code).with_color(true).render_all(&diagnostics));
+            std::process::exit(1);
+        }
+    };
+    eprint!("{}", Renderer::new(&file.name, file.source).with_color(true).render_all(&file.warnings));
     // Parse and print the variable declaration
     println!("Variable declarations:");
     file.variables.values().for_each(|var| {
@@ -73,6 +74,4 @@ fn main() {
         println!("{}", struct_);
         println!();
     });
-    file.validate_global_variable_types();
-    file.validate_functions();
 }
@@ -1,352 +1,875 @@
 use crate::{
-    ast::{structure::{Array, Object, StructDef, StructScopeItem, Subscript}, Expression, FunctionCall, FunctionDef, OperatorUse, ValidInFunctionBody, Variable},
+    ast::{structure::{Array, Assignment, Interpolation, InterpolationPart, Object, StructDef, StructScopeItem, Subscript, VarReference}, Associativity, Else, Expression, FieldAccess, Fixity, For, FunctionCall, FunctionDef, If, MethodCall, OperatorUse, UnaryOp, ValidInFunctionBody, Variable, While},
     data_type::{type_from, DataType},
+    diagnostics::{codes, Diagnostic},
     lexer::{
-        token::{self, TokenType},
+        token::{Literal, TemplatePart, Token, TokenType},
         tokenizer::Tokenizer,
     },
+    span::Span,
+    trace::TraceEvent,
 };
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
-
-static OPERATOR_PRECEDENCE: Lazy<HashMap<&'static str, u32>> = Lazy::new(|| {
-    let mut hm = HashMap::new();
-    hm.insert("=", 1);
-    hm.insert("+", 2);
-    hm.insert("-", 2);
-    hm.insert("*", 3);
-    hm.insert("/", 3);
-    hm.insert("+=", 2);
-    hm.insert("-=", 2);
-    hm.insert("*=", 3);
-    hm.insert("/=", 3);
-    hm.insert("|", 4);
-    hm.insert("==", 5);
-    hm.insert("!=", 5);
-    hm.insert(">=", 6);
-    hm.insert("<=", 6);
-    hm.insert(">", 6);
-    hm.insert("<", 6);
-    hm.insert("&&", 7);
-    hm.insert("||", 8);
-    hm
+use std::collections::{HashMap, HashSet};
+
+/// How the binary operators bind, loosest first, in the same order as C:
+///
+/// 1. `||`
+/// 2. `&&`
+/// 3. `|`
+/// 4. `==` `!=`
+/// 5. `<` `>` `<=` `>=`
+/// 6. `+` `-`
+/// 7. `*` `/` `%`
+///
+/// Higher binds tighter, and all of them are left associative, so
+/// `a - b - c` is `(a - b) - c`. Precedence 8 is left free for infix
+/// functions that should bind tighter than any of these.
+static OPERATOR_FIXITY: Lazy<HashMap<&'static str, Fixity>> = Lazy::new(|| {
+    let left = |precedence| Fixity { precedence, associativity: Associativity::Left };
+    HashMap::from([
+        ("||", left(1)),
+        ("&&", left(2)),
+        ("|", left(3)),
+        ("==", left(4)),
+        ("!=", left(4)),
+        ("<", left(5)),
+        (">", left(5)),
+        ("<=", left(5)),
+        (">=", left(5)),
+        ("+", left(6)),
+        ("-", left(6)),
+        ("*", left(7)),
+        ("/", left(7)),
+        ("%", left(7)),
+    ])
 });
 
+/// How tightly a prefix operator holds its operand: tighter than any binary
+/// operator, so `-a * b` is `(-a) * b`.
+const UNARY_PRECEDENCE: u32 = 9;
+
+/// Operators that may follow the target of an assignment statement.
+static ASSIGNMENT_OPERATORS: [&str; 6] = ["=", "+=", "-=", "*=", "/=", "%="];
+
+/// A syntax error is returned to the nearest caller able to report it.
+pub type ParseResult<T> = Result<T, Box<Diagnostic>>;
+
 pub struct Parser<'a> {
     pub tokenizer: Tokenizer<'a>,
+    /// Set while parsing the condition of an `if`, where `name {` opens the
+    /// block rather than a struct literal.
+    in_condition: bool,
+    /// Every `infix func` in the source, which are the only names that may
+    /// stand between two expressions.
+    infix_functions: HashMap<String, Fixity>,
+    /// Every struct in the source, which may be used as types.
+    struct_names: HashSet<String>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(source: &'a str) -> Parser<'a> {
-        Parser {
+        let mut parser = Parser {
             tokenizer: Tokenizer::new(source),
+            in_condition: false,
+            infix_functions: HashMap::new(),
+            struct_names: HashSet::new(),
+        };
+        parser.declare_names();
+        parser
+    }
+
+    /// Finds every `infix func` and struct up front, so they can be used
+    /// above the lines declaring them. Malformed declarations are skipped
+    /// here and reported when they are parsed for real.
+    fn declare_names(&mut self) {
+        let start = self.tokenizer.checkpoint();
+        let mut depth = 0usize;
+        while self.tokenizer.in_range() {
+            self.tokenizer.eat_lines();
+            if depth == 0 {
+                self.declare_name();
+            }
+            while let Some(token) = self.tokenizer.next_in_statement() {
+                match (&token.type_, token.value.as_str()) {
+                    (TokenType::Punctuation, "{") => depth += 1,
+                    (TokenType::Punctuation, "}") => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+        }
+        self.tokenizer.restore(start);
+    }
+
+    /// Records the infix function or struct declared by the top-level
+    /// statement starting here, if it is one.
+    fn declare_name(&mut self) {
+        let start = self.tokenizer.checkpoint();
+        if self.tokenizer.optionally_expect_keyword_of("infix") {
+            if let Ok(fixity) = self.parse_fixity()
+                && self.tokenizer.optionally_expect_keyword_of("func")
+                && let Some(name) = self.tokenizer.peek()
+                && name.type_ == TokenType::Identifier
+            {
+                self.infix_functions.insert(name.value, fixity);
+            }
+        } else {
+            self.tokenizer.optionally_expect_keyword_of("struct");
+            if let Some(name) = self.tokenizer.peek()
+                && name.type_ == TokenType::Identifier
+                && self.nth_is_punctuation(1, '{')
+            {
+                self.struct_names.insert(name.value);
+            }
+        }
+        self.tokenizer.restore(start);
+    }
+
+    /// Starts passing lexer and parser trace events to `hook`.
+    pub fn set_trace(&mut self, hook: impl Fn(&TraceEvent) + 'static) {
+        self.tokenizer.set_trace(hook);
+    }
+
+    fn trace_list_item(&self, count: usize) {
+        self.tokenizer.trace(TraceEvent::ListItem { index: count - 1, end: self.tokenizer.prev_end });
+    }
+
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        self.tokenizer.diagnostics.push(diagnostic);
+    }
+
+    /// Runs `parse_method`. If it fails the error is reported, the rest of the
+    /// statement is skipped and `error_node` stands in for it, so parsing can
+    /// carry on and find further errors.
+    fn parse_or_recover<T>(
+        &mut self,
+        parse_method: impl Fn(&mut Parser<'a>) -> ParseResult<T>,
+        error_node: impl Fn(Span) -> T,
+    ) -> T {
+        let start = self.tokenizer.next_token_start();
+        let before = self.tokenizer.checkpoint();
+        match parse_method(self) {
+            Ok(node) => node,
+            Err(diagnostic) => {
+                self.report(*diagnostic);
+                self.synchronize_statement();
+                // always make progress, but never past the end of the block
+                if self.tokenizer.checkpoint() == before && !self.next_is_punctuation('}') {
+                    self.tokenizer.next_in_statement();
+                }
+                error_node(self.tokenizer.span_from(start))
+            }
+        }
+    }
+
+    /// Ends a statement or declaration: a `;` or a line break, or the end of
+    /// the block or file, which is left for the caller.
+    pub fn expect_terminator(&mut self) -> ParseResult<()> {
+        if self.tokenizer.skip_stop() || self.next_is_punctuation('}') {
+            return Ok(());
+        }
+        match self.tokenizer.peek() {
+            Some(token) => Err(Box::new(
+                Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected `;` or a line break, found `{}`", token.value), token.span)
+                    .with_note("write each statement on a line of its own, or separate them with `;`"),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `parse_method` for something that must then end its statement.
+    fn terminated<T>(&mut self, parse_method: impl Fn(&mut Parser<'a>) -> ParseResult<T>) -> ParseResult<T> {
+        let node = parse_method(self)?;
+        self.expect_terminator()?;
+        Ok(node)
+    }
+
+    pub fn next_is_punctuation(&mut self, value: char) -> bool {
+        self.nth_is_punctuation(0, value)
+    }
+
+    /// Whether the token `n` places ahead, on the current line, is `value`.
+    fn nth_is_punctuation(&mut self, n: usize, value: char) -> bool {
+        matches!(self.tokenizer.peek_nth(n), Some(token) if token.type_ == TokenType::Punctuation && token.value.starts_with(value))
+    }
+
+    /// Skips tokens up to the end of the current line, or up to the `}` that
+    /// closes the enclosing block, whichever comes first.
+    fn synchronize_statement(&mut self) {
+        let mut depth = 0;
+        loop {
+            let checkpoint = self.tokenizer.checkpoint();
+            match self.tokenizer.next_in_statement() {
+                None => {
+                    if self.tokenizer.skip_stop() && depth == 0 {
+                        return;
+                    }
+                    if !self.tokenizer.in_range() {
+                        return;
+                    }
+                }
+                Some(token) if token.type_ == TokenType::Punctuation => match token.value.as_str() {
+                    "{" | "(" | "[" => depth += 1,
+                    "}" if depth == 0 => {
+                        self.tokenizer.restore(checkpoint);
+                        return;
+                    }
+                    "}" | ")" | "]" if depth > 0 => depth -= 1,
+                    _ => {}
+                },
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Skips tokens until something that looks like the start of a top-level
+    /// declaration: a `func`, `infix`, `var` or `struct` keyword or an identifier
+    /// beginning a line outside of any braces.
+    pub fn synchronize_declaration(&mut self) {
+        let mut depth = 0;
+        loop {
+            let checkpoint = self.tokenizer.checkpoint();
+            match self.tokenizer.next_in_statement() {
+                None => {
+                    if !self.tokenizer.in_range() {
+                        return;
+                    }
+                    if self.tokenizer.skip_stop() && depth <= 0 {
+                        self.tokenizer.eat_lines();
+                        match self.tokenizer.peek() {
+                            Some(token) if token.type_ == TokenType::Identifier => return,
+                            Some(token) if token.type_ == TokenType::Keyword && matches!(token.value.as_str(), "func" | "infix" | "var" | "struct") => return,
+                            _ => {}
+                        }
+                    }
+                }
+                Some(token) if token.type_ == TokenType::Punctuation => match token.value.as_str() {
+                    "{" => depth += 1,
+                    "}" => depth -= 1,
+                    _ => {}
+                },
+                Some(token) if token.type_ == TokenType::Keyword && depth <= 0 && matches!(token.value.as_str(), "func" | "infix" | "var" | "struct") => {
+                    self.tokenizer.restore(checkpoint);
+                    return;
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Consumes the `///` comments at the current position, along with the
+    /// line breaks after them. Returns their text, one line per comment, and
+    /// the span they cover.
+    pub fn take_doc_comments(&mut self) -> Option<(String, Span)> {
+        let mut lines = Vec::new();
+        let mut span: Option<Span> = None;
+        while let Some(token) = self.tokenizer.peek() && token.type_ == TokenType::DocComment {
+            self.tokenizer.next_in_statement();
+            self.tokenizer.eat_lines();
+            span = Some(span.map_or(token.span, |span| span.to(token.span)));
+            lines.push(token.value);
         }
+        span.map(|span| (lines.join("\n"), span))
     }
 
-    pub fn parse_var(&mut self) -> Variable<'a> {
-        let name = self.tokenizer.expect(TokenType::Identifier);
+    /// Reports doc comments that have nothing after them to document.
+    pub fn report_unused_doc_comment(&mut self, span: Span) {
+        self.report(
+            Diagnostic::warning(codes::UNUSED_DOC_COMMENT, "unused doc comment", span)
+                .with_note("doc comments only document functions, structs, struct fields and globals; use `//` for other comments"),
+        );
+    }
+
+    pub fn parse_var(&mut self) -> ParseResult<Variable<'a>> {
+        let name = self.tokenizer.expect(TokenType::Identifier)?;
         if self.tokenizer.optionally_expect_string("=") {
-            let value = self.parse_expression(0);
-            return Variable {
+            let value = self.parse_expression(0)?;
+            return Ok(Variable {
                 name: name.value,
                 type_: DataType::None,
                 value: Some(value),
-            };
+                span: self.tokenizer.span_from(name.span.start),
+                doc: None,
+            });
         }
-        let type_ = self.parse_type();
+        let type_ = self.parse_type()?;
         if self.tokenizer.optionally_expect_string("=") {
-            let value = self.parse_expression(0);
-            return Variable {
+            let value = self.parse_expression(0)?;
+            return Ok(Variable {
                 name: name.value,
                 type_,
                 value: Some(value),
-            };
+                span: self.tokenizer.span_from(name.span.start),
+                doc: None,
+            });
         }
-        return Variable {
+        Ok(Variable {
             name: name.value,
             type_,
             value: None,
-        };
+            span: self.tokenizer.span_from(name.span.start),
+            doc: None,
+        })
     }
 
 
-    pub fn parse_object_field(&mut self) -> Variable<'a> {
-        let name = self.tokenizer.expect(TokenType::Identifier);
-        self.tokenizer.expect_punctuation(':');
-        let value = self.parse_expression(0);
-        return Variable {
-                name: name.value,
-                type_: DataType::None,
-                value: Some(value), 
-            };
+    pub fn parse_object_field(&mut self) -> ParseResult<Variable<'a>> {
+        let name = self.tokenizer.expect(TokenType::Identifier)?;
+        self.tokenizer.expect_punctuation(':')?;
+        let value = self.parse_expression(0)?;
+        Ok(Variable {
+            name: name.value,
+            type_: DataType::None,
+            value: Some(value),
+            span: self.tokenizer.span_from(name.span.start),
+            doc: None,
+        })
     }
 
-    fn parse_expression_piece(&mut self) -> Expression<'a> {
-        let position_at_start = self.tokenizer.index;
+    fn parse_expression_piece(&mut self) -> ParseResult<Expression<'a>> {
+        let position_at_start = self.tokenizer.checkpoint();
 
-        let next_token = self.tokenizer.next();
-        if next_token.is_none() {
-            panic!("Unexpected end of file");
-        }
-        let next_token = next_token.unwrap();
+        let next_token = self.tokenizer.next_in_statement().ok_or_else(|| self.tokenizer.unexpected_end("an expression"))?;
 
         if next_token.type_ == TokenType::Punctuation{
             match next_token.value.as_str() {
                  "[" => {
-                    self.tokenizer.index = position_at_start;
-                    return Expression::Array(self.parse_array());
+                    self.tokenizer.restore(position_at_start);
+                    return Ok(Expression::Array(self.parse_array()?));
                 }
                  "{" => {
-                    self.tokenizer.index = position_at_start;
-                    return Expression::Object(Object { name: ("anonymous".to_string()), 
-                    fields: self.collect_custom_list(|parser| parser.parse_object_field(), '{', '}') });
+                    self.tokenizer.restore(position_at_start);
+                    let fields = self.collect_custom_list(|parser| parser.parse_object_field(), '{', '}')?;
+                    return Ok(Expression::Object(Object { name: ("anonymous".to_string()),
+                    fields, span: self.tokenizer.span_from(next_token.span.start) }));
                 }
                  "(" => {
+                    let in_condition = std::mem::replace(&mut self.in_condition, false);
                     let expr = self.parse_expression(0);
-                    self.tokenizer.expect_punctuation(')');
-                    return expr;
+                    self.in_condition = in_condition;
+                    let expr = expr?;
+                    self.tokenizer.expect_punctuation(')')?;
+                    return Ok(expr);
                 }
                 "," => {
-                     self.tokenizer.show_user_error(position_at_start, self.tokenizer.index, "did you mean to put another expression piece before the comma?".to_string());
+                    return Err(Box::new(Diagnostic::error(codes::UNEXPECTED_TOKEN, "expected an expression, found `,`", next_token.span)
+                        .with_note("did you mean to put another expression piece before the comma?")));
+                }
+                _ => {
+                    // leave closing brackets for the construct they belong to
+                    self.tokenizer.restore(position_at_start);
+                    return Err(Box::new(Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected an expression, found `{}`", next_token.value), next_token.span)));
                 }
-                _ => self.tokenizer.show_user_error(position_at_start, self.tokenizer.index, "don't know how to deal with the this punctuation char in this context".to_string()),
             }
         }
 
+        if next_token.type_ == TokenType::Operator && matches!(next_token.value.as_str(), "-" | "!") {
+            let operand = self.parse_expression(UNARY_PRECEDENCE)?;
+            return Ok(Expression::UnaryOp(UnaryOp {
+                span: next_token.span.to(operand.span()),
+                operator: next_token.value,
+                operand: Box::new(operand),
+            }));
+        }
+
         if next_token.type_ == TokenType::Identifier {
             if let Some(peek) = self.tokenizer.peek() && peek.type_ == TokenType::Punctuation{
                     match peek.value.as_str() {
                         "(" => {
-                            self.tokenizer.index = position_at_start;
-                            let func_call = self.parse_function_call();
-                            return Expression::FunctionCall(func_call);
+                            self.tokenizer.restore(position_at_start);
+                            let func_call = self.parse_function_call()?;
+                            return Ok(Expression::FunctionCall(func_call));
                         }
-                        "{" => {
-                            self.tokenizer.index = position_at_start;
-                            let struct_call = self.parse_object();
-                            return Expression::Object(struct_call);
-                        }
-                        "[" => {
-                            self.tokenizer.index = position_at_start;
-                            let subscript = self.parse_subscript();
-                            return Expression::Subscript(subscript);
+                        "{" if !self.in_condition => {
+                            self.tokenizer.restore(position_at_start);
+                            let struct_call = self.parse_object()?;
+                            return Ok(Expression::Object(struct_call));
                         }
                         _ => {}
                     }
             }
-            return Expression::VarReference(crate::ast::structure::VarReference { name: next_token.value.clone(), referring_to: None });
+            return Ok(Expression::VarReference(VarReference { name: next_token.value, referring_to: None, span: next_token.span }));
         }
 
-        Expression::Token(next_token)
+        match next_token.type_ {
+            TokenType::Number | TokenType::Float | TokenType::String | TokenType::Bool => Ok(Expression::Token(next_token)),
+            TokenType::Template => self.parse_interpolation(next_token),
+            _ => Err(Box::new(Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected an expression, found {} `{}`", next_token.type_, next_token.value), next_token.span))),
+        }
     }
 
 
-    fn parse_subscript(&mut self) -> Subscript<'a> {
-        let name = self.tokenizer.expect(TokenType::Identifier);
-        self.tokenizer.expect_punctuation('[');
-        let arg = self.parse_expression(0);
-        self.tokenizer.expect_punctuation(']');
-        return Subscript {
-            name: name.value,
-            arg: Box::new(arg),
+    /// Builds an `Interpolation` from a template string token, parsing the
+    /// code in each `{...}` as an expression of its own.
+    fn parse_interpolation(&mut self, token: Token) -> ParseResult<Expression<'a>> {
+        let template_parts = match token.literal {
+            Some(Literal::Template(parts)) => parts,
+            _ => Vec::new(),
+        };
+        let mut parts = Vec::new();
+        for part in template_parts {
+            match part {
+                TemplatePart::Text(text) if text.is_empty() => {}
+                TemplatePart::Text(text) => parts.push(InterpolationPart::Text(text)),
+                TemplatePart::Code(span) => parts.push(InterpolationPart::Expression(self.parse_embedded_expression(span)?)),
+            }
+        }
+        Ok(Expression::Interpolation(Interpolation { parts, span: token.span }))
+    }
+
+    /// Parses the code at `span`, which must hold exactly one expression.
+    fn parse_embedded_expression(&mut self, span: Span) -> ParseResult<Expression<'a>> {
+        let mut parser = Parser {
+            tokenizer: self.tokenizer.sub_tokenizer(span.start, span.end),
+            in_condition: false,
+            infix_functions: self.infix_functions.clone(),
+            struct_names: self.struct_names.clone(),
+        };
+        let expression = if parser.tokenizer.peek().is_none() {
+            Err(Box::new(Diagnostic::error(
+                codes::UNEXPECTED_TOKEN,
+                "expected an expression inside `{}`",
+                self.tokenizer.span(span.start - 1, span.end + 1),
+            )))
+        } else {
+            parser.parse_expression(0).and_then(|expression| match parser.tokenizer.peek() {
+                Some(token) => Err(Box::new(Diagnostic::error(
+                    codes::UNEXPECTED_TOKEN,
+                    format!("expected `}}`, found `{}`", token.value),
+                    token.span,
+                ))),
+                None => Ok(expression),
+            })
         };
+        self.tokenizer.diagnostics.extend(parser.tokenizer.diagnostics.into_vec());
+        expression
+    }
+
+    /// Parses the `.field`, `.method(args)` and `[index]` operations
+    /// following `object`, left to right, so `a.b[0].c()` calls `c` on an
+    /// element of `a.b`.
+    fn parse_postfix_operations(&mut self, mut object: Expression<'a>) -> ParseResult<Expression<'a>> {
+        loop {
+            let start = object.span().start;
+            if self.next_is_punctuation('[') {
+                object = Expression::Subscript(self.parse_subscript(object)?);
+                continue;
+            }
+            if !self.tokenizer.optionally_expect_punctuation('.') {
+                return Ok(object);
+            }
+            let name = self.tokenizer.expect(TokenType::Identifier)?;
+            object = if self.next_is_punctuation('(') {
+                let args = self.collect_expression_list('(', ')')?;
+                Expression::MethodCall(MethodCall { object: Box::new(object), method: name.value, args, span: self.tokenizer.span_from(start) })
+            } else {
+                Expression::FieldAccess(FieldAccess { object: Box::new(object), field: name.value, span: self.tokenizer.span_from(start) })
+            };
+        }
+    }
+
+    fn parse_subscript(&mut self, object: Expression<'a>) -> ParseResult<Subscript<'a>> {
+        let start = object.span().start;
+        self.tokenizer.expect_punctuation('[')?;
+        let arg = self.parse_expression(0)?;
+        self.tokenizer.expect_punctuation(']')?;
+        Ok(Subscript {
+            object: Box::new(object),
+            arg: Box::new(arg),
+            span: self.tokenizer.span_from(start),
+        })
     }
 
     fn collect_expression_list(
         &mut self,
         start_punctuation: char,
         end_punctuation: char,
-    ) -> Vec<Expression<'a>> {
+    ) -> ParseResult<Vec<Expression<'a>>> {
         let mut expression_list = Vec::new();
-        self.tokenizer.expect_punctuation(start_punctuation);
+        self.tokenizer.expect_punctuation(start_punctuation)?;
         while !self
             .tokenizer
             .optionally_expect_punctuation(end_punctuation)
         {
-            expression_list.push(self.parse_expression(0));
-            println!("self.tokenizer.index: {}", self.tokenizer.index);
+            expression_list.push(self.parse_expression(0)?);
+            self.trace_list_item(expression_list.len());
             if !self.tokenizer.optionally_expect_punctuation(',') {
-                self.tokenizer.expect_punctuation(end_punctuation);
+                self.tokenizer.expect_punctuation(end_punctuation)?;
                 break;
             }
         }
-        expression_list
+        Ok(expression_list)
     }
 
-    fn collect_custom_list<T, F: Fn(&mut Parser<'a>) -> T>(
+    fn collect_custom_list<T, F: Fn(&mut Parser<'a>) -> ParseResult<T>>(
         &mut self,
         parser_method: F,
         start_punctuation: char,
         end_punctuation: char,
-    ) -> Vec<T> {
+    ) -> ParseResult<Vec<T>> {
         let mut expression_list = Vec::new();
-        self.tokenizer.expect_punctuation(start_punctuation);
+        self.tokenizer.expect_punctuation(start_punctuation)?;
         while !self
             .tokenizer
             .optionally_expect_punctuation(end_punctuation)
         {
-            expression_list.push(parser_method(self));
-            println!("self.tokenizer.index: {}", self.tokenizer.index);
+            expression_list.push(parser_method(self)?);
+            self.trace_list_item(expression_list.len());
             if !self.tokenizer.optionally_expect_punctuation(',') {
-                self.tokenizer.expect_punctuation(end_punctuation);
+                self.tokenizer.expect_punctuation(end_punctuation)?;
                 break;
             }
         }
-        expression_list
+        Ok(expression_list)
     }
 
-    fn parse_array(&mut self) -> Array<'a> {
-        let elements = self.collect_expression_list('[', ']');
-        return Array { elements };
+    fn parse_array(&mut self) -> ParseResult<Array<'a>> {
+        let start = self.tokenizer.next_token_start();
+        let elements = self.collect_expression_list('[', ']')?;
+        Ok(Array { elements, span: self.tokenizer.span_from(start) })
     }
 
-    fn collect_custom_list_without_comma<T, F: Fn(&mut Parser<'a>) -> T>(
+    fn collect_custom_list_without_comma<T, F: Fn(&mut Parser<'a>) -> ParseResult<T>>(
         &mut self,
         parser_method: F,
         start_punctuation: char,
         end_punctuation: char,
-    ) -> Vec<T> {
+    ) -> ParseResult<Vec<T>> {
         let mut expression_list = Vec::new();
-        self.tokenizer.expect_punctuation(start_punctuation);
+        self.tokenizer.expect_punctuation(start_punctuation)?;
         self.tokenizer.eat_lines();
         while !self
             .tokenizer
             .optionally_expect_punctuation(end_punctuation)
         {
-            expression_list.push(parser_method(self));
-            println!("self.tokenizer.index: {}", self.tokenizer.index);
+            if !self.tokenizer.in_range() {
+                return Err(self.tokenizer.unexpected_end(&format!("`{}`", end_punctuation)));
+            }
+            expression_list.push(parser_method(self)?);
+            self.trace_list_item(expression_list.len());
             self.tokenizer.eat_lines();
         }
-        expression_list
+        Ok(expression_list)
     }
 
-    fn parse_function_call(&mut self) -> FunctionCall<'a> {
-        let name = self.tokenizer.expect(TokenType::Identifier);
-        let args = self.collect_expression_list('(', ')');
-        return FunctionCall {
+    fn parse_function_call(&mut self) -> ParseResult<FunctionCall<'a>> {
+        let name = self.tokenizer.expect(TokenType::Identifier)?;
+        let args = self.collect_expression_list('(', ')')?;
+        Ok(FunctionCall {
             name: name.value,
             args,
-        };
+            span: self.tokenizer.span_from(name.span.start),
+        })
     }
 
-    pub fn parse_function_header(&mut self) -> (String, Vec<Variable<'a>>, DataType) {
-        let name = self.tokenizer.expect(TokenType::Identifier);
-        let args = self.collect_custom_list(|parser| parser.parse_var(), '(', ')');
+    pub fn parse_function_header(&mut self) -> ParseResult<(String, Vec<Variable<'a>>, DataType)> {
+        let name = self.tokenizer.expect(TokenType::Identifier)?;
+        let args = self.collect_custom_list(|parser| parser.parse_var(), '(', ')')?;
         if self.tokenizer.optionally_expect_punctuation(':') {
-            let return_type = self.parse_type();
-            return (name.value, args, return_type);
+            let return_type = self.parse_type()?;
+            return Ok((name.value, args, return_type));
         }
         let return_type = DataType::None;
-        return (name.value, args, return_type);
+        Ok((name.value, args, return_type))
     }
 
-    fn parse_valid_in_function_body(&mut self) -> ValidInFunctionBody<'a> {
+    pub fn parse_valid_in_function_body(&mut self) -> ParseResult<ValidInFunctionBody<'a>> {
+        if let Some((_, span)) = self.take_doc_comments() {
+            self.report_unused_doc_comment(span);
+            if self.next_is_punctuation('}') {
+                return Ok(ValidInFunctionBody::Error(span));
+            }
+        }
+        let start = self.tokenizer.next_token_start();
+        if self.tokenizer.optionally_expect_keyword_of("if") {
+            return Ok(ValidInFunctionBody::If(self.parse_if(start)?));
+        }
+        if self.tokenizer.optionally_expect_keyword_of("while") {
+            let condition = self.parse_condition()?;
+            let body = self.parse_block()?;
+            return Ok(ValidInFunctionBody::While(While { condition, body, span: self.tokenizer.span_from(start) }));
+        }
+        if self.tokenizer.optionally_expect_keyword_of("for") {
+            return Ok(ValidInFunctionBody::For(self.parse_for(start)?));
+        }
+        if self.tokenizer.optionally_expect_keyword_of("break") {
+            return Ok(ValidInFunctionBody::Break(self.tokenizer.span_from(start)));
+        }
+        if self.tokenizer.optionally_expect_keyword_of("continue") {
+            return Ok(ValidInFunctionBody::Continue(self.tokenizer.span_from(start)));
+        }
         if self.tokenizer.optionally_expect_keyword_of("var") {
-            return ValidInFunctionBody::Variable(self.parse_var());
+            return Ok(ValidInFunctionBody::Variable(self.parse_var()?));
         }
         if self.tokenizer.optionally_expect_keyword_of("return") {
-            return ValidInFunctionBody::Return(self.parse_expression(0));
+            return Ok(ValidInFunctionBody::Return(self.parse_expression(0)?));
         }
-        return ValidInFunctionBody::Expression(self.parse_expression(0));
+        let expression = self.parse_expression(0)?;
+        if let Some(operator) = self.tokenizer.peek()
+            && operator.type_ == TokenType::Operator
+            && ASSIGNMENT_OPERATORS.contains(&operator.value.as_str())
+        {
+            self.tokenizer.next_in_statement();
+            let value = self.parse_expression(0)?;
+            return Ok(ValidInFunctionBody::Assignment(Assignment {
+                target: expression,
+                operator: operator.value,
+                value,
+                span: self.tokenizer.span_from(start),
+            }));
+        }
+        Ok(ValidInFunctionBody::Expression(expression))
     }
 
-    pub fn parse_function(&mut self) -> FunctionDef<'a> {
-        let (name, args, return_type) = self.parse_function_header();
-        let body = self.collect_custom_list_without_comma(
-            |parser| parser.parse_valid_in_function_body(),
+    /// A `{ ... }` block of statements.
+    fn parse_block(&mut self) -> ParseResult<Vec<ValidInFunctionBody<'a>>> {
+        self.collect_custom_list_without_comma(
+            |parser| {
+                Ok(parser.parse_or_recover(|parser| parser.terminated(Parser::parse_valid_in_function_body), ValidInFunctionBody::Error))
+            },
             '{',
             '}',
-        );
-        return FunctionDef {
+        )
+    }
+
+    fn parse_condition(&mut self) -> ParseResult<Expression<'a>> {
+        let in_condition = std::mem::replace(&mut self.in_condition, true);
+        let condition = self.parse_expression(0);
+        self.in_condition = in_condition;
+        condition
+    }
+
+    /// Parses what follows an `if` keyword starting at `start`, including any
+    /// `else if` / `else` chain. The `else` may sit on the line after the `}`.
+    fn parse_if(&mut self, start: usize) -> ParseResult<If<'a>> {
+        let condition = self.parse_condition()?;
+        let then_body = self.parse_block()?;
+        let before_else = self.tokenizer.checkpoint();
+        self.tokenizer.eat_lines();
+        let else_branch = if self.tokenizer.optionally_expect_keyword_of("else") {
+            let else_if_start = self.tokenizer.next_token_start();
+            if self.tokenizer.optionally_expect_keyword_of("if") {
+                Some(Else::If(Box::new(self.parse_if(else_if_start)?)))
+            } else {
+                Some(Else::Block(self.parse_block()?))
+            }
+        } else {
+            self.tokenizer.restore(before_else);
+            None
+        };
+        Ok(If {
+            condition,
+            then_body,
+            else_branch,
+            span: self.tokenizer.span_from(start),
+        })
+    }
+
+    /// Parses what follows a `for` keyword starting at `start`: `item in iterable { ... }`.
+    fn parse_for(&mut self, start: usize) -> ParseResult<For<'a>> {
+        let variable = self.tokenizer.expect(TokenType::Identifier)?;
+        if !self.tokenizer.optionally_expect_keyword_of("in") {
+            return Err(match self.tokenizer.peek() {
+                Some(token) => Box::new(Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected `in`, found `{}`", token.value), token.span)),
+                None => self.tokenizer.unexpected_end("`in`"),
+            });
+        }
+        let iterable = self.parse_condition()?;
+        let body = self.parse_block()?;
+        Ok(For {
+            variable: variable.value,
+            variable_span: variable.span,
+            iterable,
+            body,
+            span: self.tokenizer.span_from(start),
+        })
+    }
+
+    pub fn parse_function(&mut self) -> ParseResult<FunctionDef<'a>> {
+        let start = self.tokenizer.next_token_start();
+        let (name, args, return_type) = self.parse_function_header()?;
+        let body = self.parse_block()?;
+        Ok(FunctionDef {
             name,
             args,
             return_type,
             body,
-        };
+            span: self.tokenizer.span_from(start),
+            doc: None,
+            infix: None,
+        })
+    }
+
+    /// Parses what follows an `infix` keyword: an optional fixity, then a
+    /// function of two arguments.
+    pub fn parse_infix_function(&mut self) -> ParseResult<FunctionDef<'a>> {
+        let fixity = self.parse_fixity()?;
+        if !self.tokenizer.optionally_expect_keyword_of("func") {
+            return Err(match self.tokenizer.peek() {
+                Some(token) => Box::new(Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected `func`, found `{}`", token.value), token.span)),
+                None => self.tokenizer.unexpected_end("`func`"),
+            });
+        }
+        let function = self.parse_function()?;
+        if function.args.len() != 2 {
+            self.report(Diagnostic::error(
+                codes::INVALID_INFIX,
+                format!("infix function `{}` must take exactly two arguments, but takes {}", function.name, function.args.len()),
+                function.span,
+            ));
+        }
+        Ok(FunctionDef { infix: Some(fixity), ..function })
+    }
+
+    /// The optional `(precedence, associativity)` after `infix`. Either may be
+    /// left out: an infix function binds like a comparison and is left
+    /// associative unless it says otherwise.
+    fn parse_fixity(&mut self) -> ParseResult<Fixity> {
+        let mut fixity = Fixity { precedence: OPERATOR_FIXITY["<"].precedence, associativity: Associativity::Left };
+        if !self.next_is_punctuation('(') {
+            return Ok(fixity);
+        }
+        let settings = self.collect_custom_list(
+            |parser| parser.tokenizer.next_in_statement().ok_or_else(|| parser.tokenizer.unexpected_end("a precedence or associativity")),
+            '(',
+            ')',
+        )?;
+        for setting in settings {
+            match (&setting.type_, setting.value.as_str(), &setting.literal) {
+                (TokenType::Number, _, Some(Literal::Int(precedence))) => {
+                    if !(1..i64::from(UNARY_PRECEDENCE)).contains(precedence) {
+                        return Err(Box::new(Diagnostic::error(
+                            codes::INVALID_INFIX,
+                            format!("infix precedence must be between 1 and {}", UNARY_PRECEDENCE - 1),
+                            setting.span,
+                        )));
+                    }
+                    fixity.precedence = *precedence as u32;
+                }
+                (TokenType::Identifier, "left", _) => fixity.associativity = Associativity::Left,
+                (TokenType::Identifier, "right", _) => fixity.associativity = Associativity::Right,
+                _ => {
+                    return Err(Box::new(Diagnostic::error(
+                        codes::INVALID_INFIX,
+                        format!("expected a precedence, `left` or `right`, found `{}`", setting.value),
+                        setting.span,
+                    )))
+                }
+            }
+        }
+        Ok(fixity)
     }
 
 
-    fn parse_field_or_method(&mut self) -> StructScopeItem<'a> {
+    fn parse_field_or_method(&mut self) -> ParseResult<StructScopeItem<'a>> {
+        let doc = self.take_doc_comments();
+        if let Some((_, span)) = &doc && self.next_is_punctuation('}') {
+            self.report_unused_doc_comment(*span);
+            return Ok(StructScopeItem::Error(*span));
+        }
+        let doc = doc.map(|(text, _)| text);
         if self.tokenizer.optionally_expect_keyword_of("func") {
-            return StructScopeItem::Method(self.parse_function());
+            return Ok(StructScopeItem::Method(FunctionDef { doc, ..self.parse_function()? }));
         }
-        let start_pos = self.tokenizer.index;
-        if self.tokenizer.optionally_expect_type(TokenType::Identifier) && self.tokenizer.optionally_expect_punctuation('(') {
-            self.tokenizer.index = start_pos;
-            return StructScopeItem::Method(self.parse_function());
+        let at_name = matches!(self.tokenizer.peek_nth(0), Some(token) if token.type_ == TokenType::Identifier);
+        if at_name && self.nth_is_punctuation(1, '(') {
+            return Ok(StructScopeItem::Method(FunctionDef { doc, ..self.parse_function()? }));
         }
-        self.tokenizer.index = start_pos;
-        return StructScopeItem::Field(self.parse_var());
+        Ok(StructScopeItem::Field(Variable { doc, ..self.parse_var()? }))
     }
 
-    pub fn parse_struct(&mut self) -> StructDef<'a> {
-        let name_token = self.tokenizer.expect(TokenType::Identifier);
-        let scope_items = self.collect_custom_list_without_comma(|parser| parser.parse_field_or_method(), '{', '}');
+    pub fn parse_struct(&mut self) -> ParseResult<StructDef<'a>> {
+        let name_token = self.tokenizer.expect(TokenType::Identifier)?;
+        let scope_items = self.collect_custom_list_without_comma(
+            |parser| Ok(parser.parse_or_recover(|parser| parser.terminated(Parser::parse_field_or_method), StructScopeItem::Error)),
+            '{',
+            '}',
+        )?;
         let mut fields = vec![];
         let mut methods = vec![];
         for item in scope_items {
             match item {
                 StructScopeItem::Field(field) => fields.push(field),
                 StructScopeItem::Method(method) => methods.push(method),
+                StructScopeItem::Error(_) => {}
             }
         }
-        return StructDef {
+        Ok(StructDef {
             name: name_token.value,
             fields,
             methods,
-        };
+            span: self.tokenizer.span_from(name_token.span.start),
+            doc: None,
+        })
     }
-    pub fn parse_object(&mut self) -> Object<'a> {
-        let name_token = self.tokenizer.expect(TokenType::Identifier);
-        let fields = self.collect_custom_list(|parser| parser.parse_object_field(), '{', '}');
-        return Object {
+    pub fn parse_object(&mut self) -> ParseResult<Object<'a>> {
+        let name_token = self.tokenizer.expect(TokenType::Identifier)?;
+        let fields = self.collect_custom_list(|parser| parser.parse_object_field(), '{', '}')?;
+        Ok(Object {
             name: name_token.value,
             fields,
-        };
+            span: self.tokenizer.span_from(name_token.span.start),
+        })
     }
-    pub fn parse_expression(&mut self, left_pull: u32) -> Expression<'a> {
-        let mut left: Expression = self.parse_expression_piece();    
+    /// Parses an expression by precedence climbing, taking only the binary
+    /// operators and infix functions that bind tighter than `left_pull` and
+    /// leaving the rest to the caller.
+    pub fn parse_expression(&mut self, left_pull: u32) -> ParseResult<Expression<'a>> {
+        let piece = self.parse_expression_piece()?;
+        let mut left: Expression = self.parse_postfix_operations(piece)?;
 
-        while self.tokenizer.in_range() {
-            let possibly_greater_precedence_operand = self.tokenizer.peek();
-            if possibly_greater_precedence_operand.is_none() {
-                break;
-            }
-            let possibly_greater_precedence_operand = possibly_greater_precedence_operand.unwrap();
-            if possibly_greater_precedence_operand.type_ != TokenType::Operator && possibly_greater_precedence_operand.type_ != TokenType::Identifier {
+        while let Some(operator) = self.tokenizer.peek() {
+            let fixity = match operator.type_ {
+                TokenType::Operator => match OPERATOR_FIXITY.get(operator.value.as_str()) {
+                    Some(&fixity) => fixity,
+                    None => break,
+                },
+                TokenType::Identifier => match self.infix_functions.get(&operator.value) {
+                    Some(&fixity) => fixity,
+                    None => {
+                        return Err(Box::new(
+                            Diagnostic::error(codes::UNKNOWN_INFIX, format!("`{}` is not an infix function", operator.value), operator.span)
+                                .with_note(format!("declare it with `infix func {}(a, b)`, or call it as `{}(...)`", operator.value, operator.value)),
+                        ));
+                    }
+                },
+                _ => break,
+            };
+            if fixity.precedence <= left_pull {
                 break;
             }
-            if let Some(&precedence) =
-                OPERATOR_PRECEDENCE.get(possibly_greater_precedence_operand.value.as_str())
-            {
-                if precedence > left_pull {
-                    self.tokenizer.next(); // Consume the operator
-
-                    let right = self.parse_expression(precedence);
-
-                    left = Expression::OperatorUse(OperatorUse {
-                        operator: possibly_greater_precedence_operand.value.clone(),
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    });
-                    continue;
-                } else {
-                    break;
-                }
-            } else if possibly_greater_precedence_operand.type_ == TokenType::Identifier {
-                self.tokenizer.next();
-                left = Expression::FunctionCall(FunctionCall {
-                    name: possibly_greater_precedence_operand.value.clone(),
-                    args: vec![left, self.parse_expression(0)],
-                });
-            }
+            self.tokenizer.next_in_statement();
+            // a right associative operator lets its right operand take another
+            // of the same precedence, so `a pow b pow c` is `a pow (b pow c)`
+            let right_pull = match fixity.associativity {
+                Associativity::Left => fixity.precedence,
+                Associativity::Right => fixity.precedence - 1,
+            };
+            let right = self.parse_expression(right_pull)?;
+            let span = left.span().to(right.span());
+            left = if operator.type_ == TokenType::Operator {
+                Expression::OperatorUse(OperatorUse { operator: operator.value, left: Box::new(left), right: Box::new(right), span })
+            } else {
+                Expression::FunctionCall(FunctionCall { name: operator.value, args: vec![left, right], span })
+            };
         }
 
-        return left;
+        Ok(left)
     }
-    fn parse_type(&mut self) -> DataType {
-        let token = self.tokenizer.expect(TokenType::Identifier);
-        type_from(token.value)
+    /// A type name, or `[]T` for an array with elements of type `T`.
+    fn parse_type(&mut self) -> ParseResult<DataType> {
+        if self.tokenizer.optionally_expect_punctuation('[') {
+            self.tokenizer.expect_punctuation(']')?;
+            return Ok(DataType::Array(Box::new(self.parse_type()?)));
+        }
+        let token = self.tokenizer.expect(TokenType::Identifier)?;
+        if let Some(type_) = type_from(&token.value) {
+            return Ok(type_);
+        }
+        if self.struct_names.contains(&token.value) {
+            return Ok(DataType::Named(token.value));
+        }
+        Err(Box::new(Diagnostic::error(codes::UNKNOWN_TYPE, format!("unknown type `{}`", token.value), token.span)))
     }
 }
 
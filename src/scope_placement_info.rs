@@ -1,6 +1,27 @@
-use compiler_11::ast::FunctionDef;
+use compiler_11::{ast::{FunctionDef, ValidInFunctionBody}, data_type::DataType};
 
+/// Where in a function an expression sits: statement `index` of `body`, which
+/// is either the function body itself or a block nested inside it.
 pub struct ScopePlacementInfo<'scope_placement_info> {
     pub function_def: &'scope_placement_info FunctionDef<'scope_placement_info>,
+    pub body: &'scope_placement_info [ValidInFunctionBody<'scope_placement_info>],
     pub index: usize,
-}
\ No newline at end of file
+    /// The statement the enclosing block belongs to, `None` at the top of the function.
+    pub parent: Option<&'scope_placement_info ScopePlacementInfo<'scope_placement_info>>,
+    /// The name and type of the variable a `for` loop binds for `body`.
+    pub loop_variable: Option<(&'scope_placement_info str, DataType)>,
+}
+
+impl<'scope_placement_info> ScopePlacementInfo<'scope_placement_info> {
+    /// Whether the statement sits somewhere inside a `while` or `for` body.
+    pub fn in_loop(&self) -> bool {
+        let mut scope = self.parent;
+        while let Some(current) = scope {
+            if matches!(current.body[current.index], ValidInFunctionBody::While(_) | ValidInFunctionBody::For(_)) {
+                return true;
+            }
+            scope = current.parent;
+        }
+        false
+    }
+}
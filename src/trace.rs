@@ -0,0 +1,28 @@
+//! Opt-in tracing of what the lexer and parser do.
+//!
+//! Nothing is recorded unless a hook is installed with
+//! [`Tokenizer::set_trace`](crate::lexer::tokenizer::Tokenizer::set_trace) or
+//! [`Parser::set_trace`](crate::parser::Parser::set_trace). The hook sees every
+//! event as it happens, so it can print a parse trace, collect the events for
+//! a test, or filter for just the ones a grammar bug is about.
+
+use crate::lexer::token::Token;
+use crate::span::Span;
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
+pub enum TraceEvent<'t> {
+    /// `next_in_statement` handed out a token. Tokens only looked at by `peek` are not traced.
+    Token(&'t Token),
+    /// The lexer skipped a character it does not know.
+    UnknownCharacter { character: char, span: Span },
+    /// The tokenizer was rewound from token `from` to token `to`, counting
+    /// from the start of the source, to try another parse.
+    Backtrack { from: usize, to: usize },
+    /// The parser finished item `index` of a bracketed list, ending at byte `end`.
+    ListItem { index: usize, end: usize },
+}
+
+/// Receives trace events. Shared with sub-tokenizers, so that the code inside
+/// string interpolations is traced too.
+pub type TraceHook = Rc<dyn Fn(&TraceEvent)>;
@@ -1,4 +1,4 @@
-use compiler_11::{ast::{Expression, FunctionCall, FunctionDef, OperatorUse, ValidInFunctionBody}, data_type::DataType, lexer::token::TokenType};
+use compiler_11::{ast::{Assignment, Else, Expression, For, FunctionDef, If, ValidInFunctionBody}, data_type::DataType, diagnostics::{codes, Diagnostic, Diagnostics}};
 
 use crate::{file::File, get_type::HasType, scope_placement_info::ScopePlacementInfo};
 
@@ -10,36 +10,167 @@ use crate::{file::File, get_type::HasType, scope_placement_info::ScopePlacementI
 
 impl<'compilation_unit> File<'compilation_unit> {
     
-    fn validate_function_types(&self, function: &FunctionDef<'compilation_unit>) {
-        for (i, function_body_piece) in function.body.iter().enumerate() {
-            let function_info = ScopePlacementInfo { index: i, function_def: function };
+    fn validate_function_types(&self, function: &FunctionDef<'compilation_unit>, diagnostics: &mut Diagnostics) {
+        self.validate_body(function, &function.body, None, None, diagnostics);
+    }
+
+    /// Checks every statement of `body`, a block nested in `parent` (or the
+    /// function body itself when `parent` is `None`). `loop_variable` is the
+    /// variable bound for the block by a `for` loop, if any.
+    fn validate_body<'scope>(
+        &self,
+        function: &'scope FunctionDef<'scope>,
+        body: &'scope [ValidInFunctionBody<'scope>],
+        parent: Option<&'scope ScopePlacementInfo<'scope>>,
+        loop_variable: Option<(&'scope str, DataType)>,
+        diagnostics: &mut Diagnostics,
+    ) {
+        for (i, function_body_piece) in body.iter().enumerate() {
+            let function_info = ScopePlacementInfo { function_def: function, body, index: i, parent, loop_variable: loop_variable.clone() };
             match function_body_piece {
                 ValidInFunctionBody::Variable(variable) => {
-                    match &variable.value {
-                        Some(value) => {
-                            if value.get_type(self, &Some(function_info)) != variable.type_ {
-                                panic!("Type mismatch: expected {} but got {} on variable {}", variable.type_, value.get_type(self, &Some(ScopePlacementInfo { index: i, function_def: function })), variable.name);
-                            }
+                    if let Some(value) = &variable.value {
+                        let value_type = value.get_type(self, Some(&function_info), diagnostics);
+                        if !variable.type_.accepts(&value_type) {
+                            diagnostics.push(Diagnostic::error(
+                                codes::MISMATCHED_TYPES,
+                                format!("expected {} but got {} on variable {}", variable.type_, value_type, variable.name),
+                                value.span(),
+                            ));
                         }
-                        None => {}
                     }
                 }
                 ValidInFunctionBody::Expression(expression) => {
-                    expression.get_type(self, &Some(function_info)); //this will recursively validate the expressions type based off the types used within
+                    expression.get_type(self, Some(&function_info), diagnostics); //this will recursively validate the expressions type based off the types used within
                 }
                 ValidInFunctionBody::Return(expression) => {
-                    let expression_type = expression.get_type(self, &Some(function_info));
-                    if expression_type != function.return_type {
-                        panic!("in function {} type of return statement {} does not match return type {}", function.name, expression_type, function.return_type);
+                    let expression_type = expression.get_type(self, Some(&function_info), diagnostics);
+                    if !function.return_type.accepts(&expression_type) {
+                        diagnostics.push(Diagnostic::error(
+                            codes::MISMATCHED_TYPES,
+                            format!("in function {} type of return statement {} does not match return type {}", function.name, expression_type, function.return_type),
+                            expression.span(),
+                        ));
+                    }
+                }
+                ValidInFunctionBody::Assignment(assignment) => self.validate_assignment(assignment, &function_info, diagnostics),
+                ValidInFunctionBody::If(if_) => self.validate_if(function, if_, &function_info, diagnostics),
+                ValidInFunctionBody::While(while_) => {
+                    self.validate_condition("while", &while_.condition, &function_info, diagnostics);
+                    self.validate_body(function, &while_.body, Some(&function_info), None, diagnostics);
+                }
+                ValidInFunctionBody::For(for_) => self.validate_for(function, for_, &function_info, diagnostics),
+                ValidInFunctionBody::Break(span) | ValidInFunctionBody::Continue(span) => {
+                    if !function_info.in_loop() {
+                        let keyword = if matches!(function_body_piece, ValidInFunctionBody::Break(_)) { "break" } else { "continue" };
+                        diagnostics.push(Diagnostic::error(
+                            codes::OUTSIDE_OF_LOOP,
+                            format!("`{}` outside of a loop", keyword),
+                            *span,
+                        ));
                     }
                 }
+                ValidInFunctionBody::Error(_) => {}
             }
         }
     }
 
-    pub fn validate_functions(&self) {
+    fn validate_if<'scope>(
+        &self,
+        function: &'scope FunctionDef<'scope>,
+        if_: &'scope If<'scope>,
+        scope: &'scope ScopePlacementInfo<'scope>,
+        diagnostics: &mut Diagnostics,
+    ) {
+        self.validate_condition("if", &if_.condition, scope, diagnostics);
+        self.validate_body(function, &if_.then_body, Some(scope), None, diagnostics);
+        match &if_.else_branch {
+            Some(Else::If(else_if)) => self.validate_if(function, else_if, scope, diagnostics),
+            Some(Else::Block(body)) => self.validate_body(function, body, Some(scope), None, diagnostics),
+            None => {}
+        }
+    }
+
+    fn validate_assignment(&self, assignment: &Assignment<'compilation_unit>, scope: &ScopePlacementInfo<'_>, diagnostics: &mut Diagnostics) {
+        if !is_place(&assignment.target) {
+            diagnostics.push(
+                Diagnostic::error(codes::NOT_ASSIGNABLE, "invalid left-hand side of assignment", assignment.target.span())
+                    .with_note("only variables, array elements and struct fields can be assigned to"),
+            );
+            assignment.value.get_type(self, Some(scope), diagnostics);
+            return;
+        }
+        let target_type = assignment.target.get_type(self, Some(scope), diagnostics);
+        let value_type = assignment.value.get_type(self, Some(scope), diagnostics);
+        if !target_type.accepts(&value_type) {
+            diagnostics.push(
+                Diagnostic::error(
+                    codes::MISMATCHED_TYPES,
+                    format!("expected {} but got {} in assignment", target_type, value_type),
+                    assignment.value.span(),
+                )
+                .with_label(assignment.target.span(), format!("this is {}", target_type)),
+            );
+            return;
+        }
+        let operand_allowed = match assignment.operator.as_str() {
+            "=" => true,
+            "+=" => matches!(target_type, DataType::Int | DataType::Float | DataType::String | DataType::Unknown),
+            _ => matches!(target_type, DataType::Int | DataType::Float | DataType::Unknown),
+        };
+        if !operand_allowed {
+            diagnostics.push(Diagnostic::error(
+                codes::MISMATCHED_TYPES,
+                format!("cannot use `{}` on {}", assignment.operator, target_type),
+                assignment.span,
+            ));
+        }
+    }
+
+    fn validate_condition(&self, keyword: &str, condition: &Expression<'compilation_unit>, scope: &ScopePlacementInfo<'_>, diagnostics: &mut Diagnostics) {
+        let condition_type = condition.get_type(self, Some(scope), diagnostics);
+        if !DataType::Bool.accepts(&condition_type) {
+            diagnostics.push(Diagnostic::error(
+                codes::MISMATCHED_TYPES,
+                format!("{} condition must be bool but got {}", keyword, condition_type),
+                condition.span(),
+            ));
+        }
+    }
+
+    fn validate_for<'scope>(
+        &self,
+        function: &'scope FunctionDef<'scope>,
+        for_: &'scope For<'scope>,
+        scope: &'scope ScopePlacementInfo<'scope>,
+        diagnostics: &mut Diagnostics,
+    ) {
+        let iterable_type = for_.iterable.get_type(self, Some(scope), diagnostics);
+        let element_type = iterable_type.element_type().unwrap_or_else(|| {
+            diagnostics.push(Diagnostic::error(
+                codes::MISMATCHED_TYPES,
+                format!("can only loop over an array but got {}", iterable_type),
+                for_.iterable.span(),
+            ));
+            DataType::Unknown
+        });
+        self.validate_body(function, &for_.body, Some(scope), Some((&for_.variable, element_type)), diagnostics);
+    }
+
+    pub fn validate_functions(&self, diagnostics: &mut Diagnostics) {
         for function in self.functions.values() {
-            self.validate_function_types(function);
+            self.validate_function_types(function, diagnostics);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Whether `target` names a place that can be assigned to: a variable, or
+/// an element or field of a place.
+fn is_place(target: &Expression<'_>) -> bool {
+    match target {
+        Expression::VarReference(_) => true,
+        Expression::Subscript(subscript) => is_place(&subscript.object),
+        Expression::FieldAccess(access) => is_place(&access.object),
+        _ => false,
+    }
+}
@@ -0,0 +1,84 @@
+use compiler_11::diagnostics::{codes, Diagnostic, Diagnostics, Renderer};
+use compiler_11::lexer::token::TokenType;
+use compiler_11::lexer::tokenizer::Tokenizer;
+use compiler_11::parser::Parser;
+use compiler_11::span::Span;
+
+fn span_of(source: &str, text: &str) -> Span {
+    let start = source.find(text).unwrap();
+    Tokenizer::new(source).span(start, start + text.len())
+}
+
+#[test]
+fn renders_snippet_with_underline() {
+    let source = "var a int = 1\nvar b int = a + \"x\"\n";
+    let diagnostic = Diagnostic::error(codes::MISMATCHED_TYPES, "mismatched types: int + string", span_of(source, "a + \"x\""))
+        .with_label(span_of(source, "\"x\""), "this is string")
+        .with_note("both sides of `+` must have the same type");
+    let expected = "\
+error[E0301]: mismatched types: int + string
+ --> main:2:13
+  |
+2 | var b int = a + \"x\"
+  |             ^^^^^^^
+  |                 --- this is string
+  = note: both sides of `+` must have the same type
+";
+    assert_eq!(Renderer::new("main", source).render(&diagnostic), expected);
+}
+
+#[test]
+fn renders_labels_on_other_lines() {
+    let source = "func f(x int): int { return 1 }\n\n\nvar y int = f(\"no\")";
+    let diagnostic = Diagnostic::error(codes::MISMATCHED_TYPES, "expected int but got string", span_of(source, "\"no\""))
+        .with_label(span_of(source, "x int"), "parameter declared here");
+    let expected = "\
+error[E0301]: expected int but got string
+ --> main:4:15
+  |
+1 | func f(x int): int { return 1 }
+  |        ----- parameter declared here
+...
+4 | var y int = f(\"no\")
+  |               ^^^^
+";
+    assert_eq!(Renderer::new("main", source).render(&diagnostic), expected);
+}
+
+#[test]
+fn renders_caret_at_end_of_file() {
+    let source = "var a int = (1";
+    let diagnostic = Diagnostic::error(codes::UNEXPECTED_EOF, "expected `)`, found end of file", Tokenizer::new(source).span(14, 14));
+    let rendered = Renderer::new("main", source).render(&diagnostic);
+    assert!(rendered.ends_with("1 | var a int = (1\n  |               ^\n"), "{}", rendered);
+}
+
+#[test]
+fn plain_mode_has_no_escape_codes() {
+    colored::control::set_override(true);
+    let source = "var a int = \"x\"";
+    let diagnostic = Diagnostic::error(codes::MISMATCHED_TYPES, "mismatched", span_of(source, "\"x\""));
+    assert!(!Renderer::new("main", source).render(&diagnostic).contains('\x1b'));
+    assert!(Renderer::new("main", source).with_color(true).render(&diagnostic).contains('\x1b'));
+}
+
+#[test]
+fn renders_spans_at_crlf_line_breaks() {
+    let source = "var a int = (1\r\nvar b int = 2\r\n";
+    let mut parser = Parser::new(source);
+    parser.tokenizer.expect(TokenType::Keyword).unwrap();
+    let diagnostic = parser.parse_var().unwrap_err();
+    assert_eq!(diagnostic.message, "expected `)`, found end of line");
+    let rendered = Renderer::new("main", source).render(&diagnostic);
+    assert!(rendered.ends_with("1 | var a int = (1\n  |               ^\n"), "{}", rendered);
+}
+
+#[test]
+fn identical_diagnostics_are_recorded_once() {
+    let mut diagnostics = Diagnostics::new();
+    for start in (0..20_000).chain(0..20_000) {
+        diagnostics.push(Diagnostic::error(codes::UNKNOWN_CHARACTER, "unknown character `@`", Span::new(start, start + 1, 1, start + 1)));
+    }
+    diagnostics.push(Diagnostic::error(codes::UNKNOWN_CHARACTER, "unknown character `@`", Span::new(0, 1, 1, 1)).with_note("a different note"));
+    assert_eq!(diagnostics.len(), 20_001);
+}
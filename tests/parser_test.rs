@@ -1,10 +1,12 @@
 use compiler_11::{
     ast::{
-        structure::StructDef, AstComparable, Expression, FunctionCall, FunctionDef, OperatorUse, ValidInFunctionBody, Variable
+        structure::{StructDef, Subscript, VarReference}, Assignment, Associativity, Fixity, AstComparable, Else, Expression, For, FunctionCall, FunctionDef, If, Interpolation, InterpolationPart, OperatorUse, UnaryOp, ValidInFunctionBody, Variable, While
     },
     data_type::DataType,
+    diagnostics::codes,
     lexer::token::{Token, TokenType},
     parser::Parser,
+    span::Span,
 };
 
 // Helper macro to assert equality with detailed error reporting
@@ -25,22 +27,28 @@ macro_rules! assert_ast_eq {
 
 // Helper function to create a simple token
 fn token(ty: TokenType, value: &str) -> Token {
-    Token {
-        type_: ty,
-        value: value.to_string(),
-    }
+    Token::new(ty, value, Span::default())
 }
 
 // Helper function to create a simple expression
-fn expr_token(ty: TokenType, value: &str) -> Expression {
+fn expr_token(ty: TokenType, value: &str) -> Expression<'_> {
     Expression::Token(token(ty, value))
 }
 
+// Helper function to create a reference to a variable
+fn var_ref(name: &str) -> Expression<'_> {
+    Expression::VarReference(VarReference {
+        name: name.to_string(),
+        referring_to: None,
+        span: Span::default(),
+    })
+}
+
 #[test]
 fn test_parse_variable_declaration() {
     let mut p = Parser::new("var num int = 10");
-    p.tokenizer.expect(TokenType::Keyword);
-    let var = p.parse_var();
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let var = p.parse_var().unwrap();
     assert_ast_eq!(var.name, "num".to_string());
     assert_ast_eq!(var.type_, DataType::Int);
     if let Some(Expression::Token(token)) = var.value {
@@ -55,31 +63,39 @@ fn test_parse_variable_declaration() {
 fn test_parse_function() {
     let code = "my_func(a int, b int,): int { return a + b }";
     let mut p = Parser::new(code);
-    let function = p.parse_function();
+    let function = p.parse_function().unwrap();
 
     // Define the expected AST structure
     let expected = FunctionDef {
+        doc: None,
+        infix: None,
         name: "my_func".to_string(),
         args: vec![
             Variable {
+                doc: None,
                 name: "a".to_string(),
                 type_: DataType::Int,
                 value: None,
+                span: Span::default(),
             },
             Variable {
+                doc: None,
                 name: "b".to_string(),
                 type_: DataType::Int,
                 value: None,
+                span: Span::default(),
             },
         ],
         return_type: DataType::Int,
         body: vec![ValidInFunctionBody::Return(Expression::OperatorUse(
             OperatorUse {
                 operator: "+".to_string(),
-                left: Box::new(expr_token(TokenType::Identifier, "a")),
-                right: Box::new(expr_token(TokenType::Identifier, "b")),
+                left: Box::new(var_ref("a")),
+                right: Box::new(var_ref("b")),
+                span: Span::default(),
             },
         ))],
+        span: Span::default(),
     };
 
     // Compare the actual and expected ASTs
@@ -87,27 +103,35 @@ fn test_parse_function() {
 
     // Define the expected function structure
     let expected = FunctionDef {
+        doc: None,
+        infix: None,
         name: "my_func".to_string(),
         args: vec![
             Variable {
+                doc: None,
                 name: "a".to_string(),
                 type_: DataType::Int,
                 value: None,
+                span: Span::default(),
             },
             Variable {
+                doc: None,
                 name: "b".to_string(),
                 type_: DataType::Int,
                 value: None,
+                span: Span::default(),
             },
         ],
         return_type: DataType::Int,
         body: vec![ValidInFunctionBody::Return(Expression::OperatorUse(
             OperatorUse {
                 operator: "+".to_string(),
-                left: Box::new(expr_token(TokenType::Identifier, "a")),
-                right: Box::new(expr_token(TokenType::Identifier, "b")),
+                left: Box::new(var_ref("a")),
+                right: Box::new(var_ref("b")),
+                span: Span::default(),
             },
         ))],
+        span: Span::default(),
     };
 
     // Compare the actual and expected ASTs
@@ -118,23 +142,27 @@ fn test_parse_function() {
 fn test_parse_function_header() {
     let code = "my_func(a int, b int,): int";
     let mut p = Parser::new(code);
-    let (name, args, return_type) = p.parse_function_header();
+    let (name, args, return_type) = p.parse_function_header().unwrap();
 
     // Verify the parsed header
     assert_eq!(name, "my_func");
     assert_eq!(args.len(), 2);
 
     // Create expected variables for comparison
-    let expected_args = vec![
+    let expected_args = [
         Variable {
+            doc: None,
             name: "a".to_string(),
             type_: DataType::Int,
             value: None,
+            span: Span::default(),
         },
         Variable {
+            doc: None,
             name: "b".to_string(),
             type_: DataType::Int,
             value: None,
+            span: Span::default(),
         },
     ];
 
@@ -150,8 +178,8 @@ fn test_parse_function_header() {
 fn test_parse_complex_expression() {
     let code = "var result int = (a + b) * (c - d) / e";
     let mut p = Parser::new(code);
-    p.tokenizer.expect(TokenType::Keyword);
-    let var = p.parse_var();
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let var = p.parse_var().unwrap();
 
     // Verify the variable structure
     assert_eq!(var.name, "result");
@@ -164,16 +192,20 @@ fn test_parse_complex_expression() {
             operator: "*".to_string(),
             left: Box::new(Expression::OperatorUse(OperatorUse {
                 operator: "+".to_string(),
-                left: Box::new(expr_token(TokenType::Identifier, "a")),
-                right: Box::new(expr_token(TokenType::Identifier, "b")),
+                left: Box::new(var_ref("a")),
+                right: Box::new(var_ref("b")),
+                span: Span::default(),
             })),
             right: Box::new(Expression::OperatorUse(OperatorUse {
                 operator: "-".to_string(),
-                left: Box::new(expr_token(TokenType::Identifier, "c")),
-                right: Box::new(expr_token(TokenType::Identifier, "d")),
+                left: Box::new(var_ref("c")),
+                right: Box::new(var_ref("d")),
+                span: Span::default(),
             })),
+            span: Span::default(),
         })),
-        right: Box::new(expr_token(TokenType::Identifier, "e")),
+        right: Box::new(var_ref("e")),
+        span: Span::default(),
     });
 
     // Compare the actual and expected ASTs
@@ -184,14 +216,15 @@ fn test_parse_complex_expression() {
 fn test_parse_var_with_value() {
     let code = "var result bool = a == b";
     let mut p = Parser::new(code);
-    p.tokenizer.expect(TokenType::Keyword);
-    let var = p.parse_var();
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let var = p.parse_var().unwrap();
 
     // Define the expected AST structure based on actual parser behavior
     let expected_value = Expression::OperatorUse(OperatorUse {
         operator: "==".to_string(),
-        left: Box::new(expr_token(TokenType::Identifier, "a")),
-        right: Box::new(expr_token(TokenType::Identifier, "b")),
+        left: Box::new(var_ref("a")),
+        right: Box::new(var_ref("b")),
+        span: Span::default(),
     });
 
     assert_ast_eq!(var.value.unwrap(), expected_value);
@@ -201,48 +234,55 @@ fn test_parse_var_with_value() {
 fn test_parse_function_call_expression() {
     let code = "result = max(a + b, c * d,)";
     let mut p = Parser::new(code);
-    let parsed_expression = p.parse_expression(0);
+    let parsed_statement = p.parse_valid_in_function_body().unwrap();
 
     // Define the expected AST structure based on actual parser behavior
-    let expected_expression: Expression = Expression::OperatorUse(OperatorUse {
+    let expected_statement = ValidInFunctionBody::Assignment(Assignment {
         operator: "=".to_string(),
-        left: Box::new(expr_token(TokenType::Identifier, "result")),
-        right: Box::new(Expression::FunctionCall(FunctionCall {
+        target: var_ref("result"),
+        value: Expression::FunctionCall(FunctionCall {
             name: "max".to_string(),
             args: vec![
                 Expression::OperatorUse(OperatorUse {
                     operator: "+".to_string(),
-                    left: Box::new(expr_token(TokenType::Identifier, "a")),
-                    right: Box::new(expr_token(TokenType::Identifier, "b")),
+                    left: Box::new(var_ref("a")),
+                    right: Box::new(var_ref("b")),
+                    span: Span::default(),
                 }),
                 Expression::OperatorUse(OperatorUse {
                     operator: "*".to_string(),
-                    left: Box::new(expr_token(TokenType::Identifier, "c")),
-                    right: Box::new(expr_token(TokenType::Identifier, "d")),
+                    left: Box::new(var_ref("c")),
+                    right: Box::new(var_ref("d")),
+                    span: Span::default(),
                 }),
             ],
-        })),
+            span: Span::default(),
+        }),
+        span: Span::default(),
     });
 
-    assert_ast_eq!(parsed_expression, expected_expression);
+    assert_ast_eq!(parsed_statement, expected_statement);
 }
 
 #[test]
 fn test_parse_var() {
     let code = "var result int = a + b";
     let mut p = Parser::new(code);
-    p.tokenizer.expect(TokenType::Keyword);
-    let parsed_var = p.parse_var();
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let parsed_var = p.parse_var().unwrap();
 
     // Define the expected AST structure based on actual parser behavior
     let expected_var = Variable {
+        doc: None,
         name: "result".to_string(),
         type_: DataType::Int,
         value: Some(Expression::OperatorUse(OperatorUse {
             operator: "+".to_string(),
-            left: Box::new(expr_token(TokenType::Identifier, "a")),
-            right: Box::new(expr_token(TokenType::Identifier, "b")),
+            left: Box::new(var_ref("a")),
+            right: Box::new(var_ref("b")),
+            span: Span::default(),
         })),
+        span: Span::default(),
     };
 
     assert_ast_eq!(parsed_var, expected_var);
@@ -252,18 +292,22 @@ fn test_parse_var() {
 fn test_parse_function2() {
     let code = "func main() { send_message() }";
     let mut p = Parser::new(code);
-    p.tokenizer.expect(TokenType::Keyword);
-    let parsed_function = p.parse_function();
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let parsed_function = p.parse_function().unwrap();
 
     // Define the expected AST structure based on actual parser behavior
     let expected_function = FunctionDef {
+        doc: None,
+        infix: None,
         name: "main".to_string(),
         args: vec![],
         return_type: DataType::None,
         body: vec![ValidInFunctionBody::Expression(Expression::FunctionCall(FunctionCall {
             name: "send_message".to_string(),
             args: vec![],
+            span: Span::default(),
         }))],
+        span: Span::default(),
     };
 
     assert_ast_eq!(parsed_function, expected_function);
@@ -280,49 +324,432 @@ fn test_parse_struct() {
         } 
     }";
     let mut p = Parser::new(code);
-    p.tokenizer.expect(TokenType::Keyword);
-    let parsed_struct = p.parse_struct();
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let parsed_struct = p.parse_struct().unwrap();
 
     // Define the expected AST structure based on actual parser behavior
     let expected_struct = StructDef {
+        doc: None,
         name: "Message".to_string(),
         fields: vec![
             Variable {
+                doc: None,
                 name: "text".to_string(),
                 type_: DataType::String,
-                value: Some(Expression::Token(Token {
-                    type_: TokenType::String,
-                    value: "shmuli boy".to_string(),
-                })),
+                value: Some(expr_token(TokenType::String, "shmuli boy")),
+                span: Span::default(),
             },
             Variable {
+                doc: None,
                 name: "id".to_string(),
                 type_: DataType::Int,
                 value: None,
+                span: Span::default(),
             },
         ],
         methods: vec![FunctionDef {
+            doc: None,
+            infix: None,
             name: "add".to_string(),
             args: vec![
                 Variable {
+                    doc: None,
                     name: "a".to_string(),
                     type_: DataType::Int,
                     value: None,
+                    span: Span::default(),
                 },
                 Variable {
+                    doc: None,
                     name: "b".to_string(),
                     type_: DataType::Int,
                     value: None,
+                    span: Span::default(),
                 },
             ],
             return_type: DataType::Int,
             body: vec![ValidInFunctionBody::Return(Expression::OperatorUse(OperatorUse {
                 operator: "+".to_string(),
-                left: Box::new(expr_token(TokenType::Identifier, "id")),
+                left: Box::new(var_ref("id")),
                 right: Box::new(expr_token(TokenType::Number, "1")),
+                span: Span::default(),
             }))],
+            span: Span::default(),
         }],
+        span: Span::default(),
     };
 
     assert_ast_eq!(parsed_struct, expected_struct);
 }
+
+#[test]
+fn test_nodes_carry_spans() {
+    let code = "func add(a int, b int): int {\n    return a + add(b, 1)\n}";
+    let mut p = Parser::new(code);
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let function = p.parse_function().unwrap();
+    assert_eq!(&code[function.span.start..function.span.end], &code[5..]);
+    assert_eq!((function.args[1].span.line, function.args[1].span.col), (1, 17));
+
+    let ValidInFunctionBody::Return(Expression::OperatorUse(sum)) = &function.body[0] else {
+        panic!("Expected a return of an operator use");
+    };
+    assert_eq!(&code[sum.span.start..sum.span.end], "a + add(b, 1)");
+    assert_eq!((sum.span.line, sum.span.col), (2, 12));
+    assert_eq!(&code[sum.right.span().start..sum.right.span().end], "add(b, 1)");
+}
+
+#[test]
+fn test_syntax_errors_are_returned_not_panicked() {
+    let mut p = Parser::new("var count int = (1 + 2");
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let error = p.parse_var().unwrap_err();
+    assert_eq!(error.code, codes::UNEXPECTED_EOF);
+    assert_eq!((error.span.line, error.span.col), (1, 23));
+
+    let mut p = Parser::new("var count integer = 1");
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let error = p.parse_var().unwrap_err();
+    assert_eq!(error.code, codes::UNKNOWN_TYPE);
+    assert_eq!(error.message, "unknown type `integer`");
+    assert_eq!((error.span.start, error.span.end), (10, 17));
+}
+
+#[test]
+fn test_bad_statements_become_error_nodes() {
+    let code = "func f(): int {
+        var a int = (1 + 2
+        return ]
+        return a
+    }";
+    let mut p = Parser::new(code);
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let function = p.parse_function().unwrap();
+
+    assert_eq!(function.body.len(), 3);
+    assert!(matches!(function.body[0], ValidInFunctionBody::Error(_)));
+    assert!(matches!(function.body[1], ValidInFunctionBody::Error(_)));
+    assert_ast_eq!(function.body[2], ValidInFunctionBody::Return(var_ref("a")));
+    assert_eq!(p.tokenizer.diagnostics.len(), 2);
+}
+
+#[test]
+fn test_parse_if_else_chain() {
+    let code = "func sign(n int, positive bool, zero bool): int {
+        if positive {
+            return 1
+        } else if zero {
+            return 0
+        }
+        else {
+            return n
+        }
+    }";
+    let mut p = Parser::new(code);
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let function = p.parse_function().unwrap();
+
+    let expected = ValidInFunctionBody::If(If {
+        condition: var_ref("positive"),
+        then_body: vec![ValidInFunctionBody::Return(expr_token(TokenType::Number, "1"))],
+        else_branch: Some(Else::If(Box::new(If {
+            condition: var_ref("zero"),
+            then_body: vec![ValidInFunctionBody::Return(expr_token(TokenType::Number, "0"))],
+            else_branch: Some(Else::Block(vec![ValidInFunctionBody::Return(var_ref("n"))])),
+            span: Span::default(),
+        }))),
+        span: Span::default(),
+    });
+    assert_eq!(function.body.len(), 1);
+    assert_ast_eq!(function.body[0], expected);
+}
+
+#[test]
+fn test_if_condition_is_not_a_struct_literal() {
+    let code = "func f(done bool) { if done { stop() } }";
+    let mut p = Parser::new(code);
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let function = p.parse_function().unwrap();
+    let ValidInFunctionBody::If(if_) = &function.body[0] else {
+        panic!("Expected an if statement");
+    };
+    assert_ast_eq!(if_.condition, var_ref("done"));
+    assert!(if_.else_branch.is_none());
+}
+
+#[test]
+fn test_parse_loops() {
+    let code = "func f(xs []int, going bool) {
+        while going {
+            for x in xs {
+                continue
+            }
+            break
+        }
+    }";
+    let mut p = Parser::new(code);
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let function = p.parse_function().unwrap();
+
+    assert_eq!(function.args[0].type_, DataType::Array(Box::new(DataType::Int)));
+    let expected = ValidInFunctionBody::While(While {
+        condition: var_ref("going"),
+        body: vec![
+            ValidInFunctionBody::For(For {
+                variable: "x".to_string(),
+                variable_span: Span::default(),
+                iterable: var_ref("xs"),
+                body: vec![ValidInFunctionBody::Continue(Span::default())],
+                span: Span::default(),
+            }),
+            ValidInFunctionBody::Break(Span::default()),
+        ],
+        span: Span::default(),
+    });
+    assert_eq!(function.body.len(), 1);
+    assert_ast_eq!(function.body[0], expected);
+}
+
+#[test]
+fn test_parse_compound_assignment_to_subscript() {
+    let code = "counts[0] += 1";
+    let mut p = Parser::new(code);
+    let parsed_statement = p.parse_valid_in_function_body().unwrap();
+    let expected_statement = ValidInFunctionBody::Assignment(Assignment {
+        target: Expression::Subscript(Subscript {
+            object: Box::new(var_ref("counts")),
+            arg: Box::new(expr_token(TokenType::Number, "0")),
+            span: Span::default(),
+        }),
+        operator: "+=".to_string(),
+        value: expr_token(TokenType::Number, "1"),
+        span: Span::default(),
+    });
+    assert_ast_eq!(parsed_statement, expected_statement);
+}
+
+#[test]
+fn test_parse_unary_operators() {
+    let mut p = Parser::new("-a * -2 - !done");
+    let parsed_expression = p.parse_expression(0).unwrap();
+    let unary = |operator: &str, operand: Expression<'static>| Expression::UnaryOp(UnaryOp {
+        operator: operator.to_string(),
+        operand: Box::new(operand),
+        span: Span::default(),
+    });
+    let expected_expression = Expression::OperatorUse(OperatorUse {
+        left: Box::new(Expression::OperatorUse(OperatorUse {
+            left: Box::new(unary("-", var_ref("a"))),
+            operator: "*".to_string(),
+            right: Box::new(unary("-", expr_token(TokenType::Number, "2"))),
+            span: Span::default(),
+        })),
+        operator: "-".to_string(),
+        right: Box::new(unary("!", var_ref("done"))),
+        span: Span::default(),
+    });
+    assert_ast_eq!(parsed_expression, expected_expression);
+}
+
+#[test]
+fn test_parse_interpolation() {
+    let mut p = Parser::new("\"{name} is {age + 1}\"");
+    let parsed_expression = p.parse_expression(0).unwrap();
+    let expected_expression = Expression::Interpolation(Interpolation {
+        parts: vec![
+            InterpolationPart::Expression(var_ref("name")),
+            InterpolationPart::Text(" is ".to_string()),
+            InterpolationPart::Expression(Expression::OperatorUse(OperatorUse {
+                left: Box::new(var_ref("age")),
+                operator: "+".to_string(),
+                right: Box::new(expr_token(TokenType::Number, "1")),
+                span: Span::default(),
+            })),
+        ],
+        span: Span::default(),
+    });
+    assert_ast_eq!(parsed_expression, expected_expression);
+    let Expression::Interpolation(interpolation) = &parsed_expression else { unreachable!() };
+    let InterpolationPart::Expression(age) = &interpolation.parts[2] else { unreachable!() };
+    assert_eq!((age.span().start, age.span().end), (12, 19));
+}
+
+#[test]
+fn test_bad_interpolations_are_reported() {
+    let code = "func f() {\n    g(\"{}\")\n    g(\"{a)}\")\n    g(\"{a +}\")\n}";
+    let mut p = Parser::new(code);
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    p.parse_function().unwrap();
+    let messages: Vec<_> = p.tokenizer.diagnostics.iter().map(|d| (d.span.line, d.message.clone())).collect();
+    assert_eq!(messages, vec![
+        (2, "expected an expression inside `{}`".to_string()),
+        (3, "expected `}`, found `)`".to_string()),
+        (4, "expected an expression, found `}`".to_string()),
+    ]);
+}
+
+#[test]
+fn test_trace_reports_list_items() {
+    let items = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let mut parser = Parser::new("max(a, b + 1, c)");
+    let sink = std::rc::Rc::clone(&items);
+    parser.set_trace(move |event| {
+        if let compiler_11::trace::TraceEvent::ListItem { index, end } = event {
+            sink.borrow_mut().push((*index, *end));
+        }
+    });
+    parser.parse_expression(0).unwrap();
+    assert_eq!(*items.borrow(), vec![(0, 5), (1, 12), (2, 15)]);
+}
+
+#[test]
+fn test_statements_end_at_terminators() {
+    let code = "func f(a int, b int): int {
+        a = b
+        (b)
+        a = 1; b = 2
+        return a +
+            b
+    }";
+    let mut p = Parser::new(code);
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let function = p.parse_function().unwrap();
+
+    assert_eq!(function.body.len(), 5);
+    assert!(matches!(function.body[0], ValidInFunctionBody::Assignment(_)));
+    assert_ast_eq!(function.body[1], ValidInFunctionBody::Expression(var_ref("b")));
+    assert!(matches!(function.body[3], ValidInFunctionBody::Assignment(_)));
+    assert_ast_eq!(function.body[4], ValidInFunctionBody::Return(Expression::OperatorUse(OperatorUse {
+        operator: "+".to_string(),
+        left: Box::new(var_ref("a")),
+        right: Box::new(var_ref("b")),
+        span: Span::default(),
+    })));
+    assert!(p.tokenizer.diagnostics.is_empty());
+}
+
+#[test]
+fn test_statements_on_one_line_need_a_semicolon() {
+    let mut p = Parser::new("func f(): int { return 1 2 }");
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let function = p.parse_function().unwrap();
+    assert!(matches!(function.body[0], ValidInFunctionBody::Error(_)));
+    let diagnostics = p.tokenizer.diagnostics.into_vec();
+    assert_eq!(diagnostics[0].message, "expected `;` or a line break, found `2`");
+}
+
+#[test]
+fn test_infix_functions_use_their_fixity() {
+    let code = "infix(8, right) func pow(a int, b int): int { return a }
+        infix(1, left) func max(a int, b int): int { return a }
+        1 + a pow b pow c max d";
+    let mut p = Parser::new(code);
+    p.tokenizer.eat_lines();
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    let pow = p.parse_infix_function().unwrap();
+    assert_eq!(pow.infix, Some(Fixity { precedence: 8, associativity: Associativity::Right }));
+    p.tokenizer.eat_lines();
+    p.tokenizer.expect(TokenType::Keyword).unwrap();
+    p.parse_infix_function().unwrap();
+    p.tokenizer.eat_lines();
+
+    let call = |name: &str, left, right| {
+        Expression::FunctionCall(FunctionCall { name: name.to_string(), args: vec![left, right], span: Span::default() })
+    };
+    let expected = call(
+        "max",
+        Expression::OperatorUse(OperatorUse {
+            operator: "+".to_string(),
+            left: Box::new(expr_token(TokenType::Number, "1")),
+            right: Box::new(call("pow", var_ref("a"), call("pow", var_ref("b"), var_ref("c")))),
+            span: Span::default(),
+        }),
+        var_ref("d"),
+    );
+    assert_ast_eq!(p.parse_expression(0).unwrap(), expected);
+}
+
+/// Writes `expression` with every operation in parentheses, to show how it grouped.
+fn grouping(expression: &Expression) -> String {
+    match expression {
+        Expression::OperatorUse(op) => format!("({} {} {})", grouping(&op.left), op.operator, grouping(&op.right)),
+        Expression::UnaryOp(op) => format!("({}{})", op.operator, grouping(&op.operand)),
+        Expression::FunctionCall(call) => {
+            let args: Vec<_> = call.args.iter().map(grouping).collect();
+            format!("{}({})", call.name, args.join(", "))
+        }
+        Expression::Subscript(subscript) => format!("({}[{}])", grouping(&subscript.object), grouping(&subscript.arg)),
+        Expression::FieldAccess(access) => format!("({}.{})", grouping(&access.object), access.field),
+        Expression::MethodCall(call) => {
+            let args: Vec<_> = call.args.iter().map(grouping).collect();
+            format!("({}.{}({}))", grouping(&call.object), call.method, args.join(", "))
+        }
+        Expression::VarReference(var) => var.name.clone(),
+        Expression::Token(token) => token.value.clone(),
+        other => panic!("unexpected expression {:?}", other),
+    }
+}
+
+#[test]
+fn test_operator_precedence_and_associativity() {
+    let declarations = "infix(8, right) func pow(a int, b int): int { return a }\ninfix func near(a int, b int): bool { return true }\n";
+    let cases = [
+        ("1 + 2 * 3", "(1 + (2 * 3))"),
+        ("1 * 2 + 3", "((1 * 2) + 3)"),
+        ("a + b % c", "(a + (b % c))"),
+        ("a - b - c", "((a - b) - c)"),
+        ("a / b * c", "((a / b) * c)"),
+        ("a < b + 1", "(a < (b + 1))"),
+        ("a == b < c", "(a == (b < c))"),
+        ("a != b == c", "((a != b) == c)"),
+        ("a | b == c", "(a | (b == c))"),
+        ("a && b | c", "(a && (b | c))"),
+        ("a || b && c", "(a || (b && c))"),
+        ("1 > 0 || false", "((1 > 0) || false)"),
+        ("-a * b", "((-a) * b)"),
+        ("!a && b", "((!a) && b)"),
+        ("(a || b) && c", "((a || b) && c)"),
+        ("a pow b pow c", "pow(a, pow(b, c))"),
+        ("-a pow b * c", "(pow((-a), b) * c)"),
+        ("a + 1 near b && c", "(near((a + 1), b) && c)"),
+    ];
+    for (source, expected) in cases {
+        let code = format!("{}{}", declarations, source);
+        let mut p = Parser::new(&code);
+        // skip past the declarations to the expression on the last line
+        while p.tokenizer.next_token_start() < declarations.len() {
+            if p.tokenizer.next_in_statement().is_none() {
+                p.tokenizer.skip_stop();
+            }
+        }
+        let expression = p.parse_expression(0).unwrap();
+        assert_eq!(grouping(&expression), expected, "grouping of `{}`", source);
+    }
+}
+
+#[test]
+fn test_field_access_and_method_calls_chain() {
+    let cases = [
+        ("p.name", "(p.name)"),
+        ("p.greet()", "(p.greet())"),
+        ("a.b.c", "((a.b).c)"),
+        ("a.b(1, x.y).c", "((a.b(1, (x.y))).c)"),
+        ("f(p).age", "(f(p).age)"),
+        ("(a + b).c", "((a + b).c)"),
+        ("-p.age * 2", "((-(p.age)) * 2)"),
+        ("p.age + q.age", "((p.age) + (q.age))"),
+        ("p.items[0]", "((p.items)[0])"),
+        ("p.f()[i + 1].g", "(((p.f())[(i + 1)]).g)"),
+        ("a[0][1]", "((a[0])[1])"),
+        ("-a[0]", "(-(a[0]))"),
+    ];
+    for (source, expected) in cases {
+        let mut parser = Parser::new(source);
+        let expression = parser.parse_expression(0).unwrap();
+        assert_eq!(grouping(&expression), expected, "grouping of `{}`", source);
+        assert!(parser.tokenizer.peek().is_none(), "`{}` was not parsed to the end", source);
+    }
+
+    let mut parser = Parser::new("p.");
+    assert_eq!(parser.parse_expression(0).unwrap_err().code, codes::UNEXPECTED_EOF);
+}
@@ -1,9 +1,13 @@
-use compiler_11::lexer::token::{Token, TokenType};
-use compiler_11::lexer::tokenizer::Tokenizer;
+use compiler_11::diagnostics::codes;
+use compiler_11::lexer::token::{Literal, TemplatePart, TokenType, TriviaKind};
+use compiler_11::lexer::tokenizer::{Line, LineState, OpenConstruct, Tokenizer};
+use compiler_11::trace::TraceEvent;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 fn collect_types_and_values(mut t: Tokenizer<'_>) -> Vec<(TokenType, String)> {
     let mut out = Vec::new();
-    while let Some(tok) = t.next() {
+    while let Some(tok) = t.next_in_statement() {
         out.push((tok.type_.clone(), tok.value.clone()))
     }
     out
@@ -42,7 +46,7 @@ fn tokenize_strings_and_punctuation() {
 #[test]
 fn tokenize_keywords_vs_identifiers() {
     let src = "var x = func".to_string();
-    let mut t = Tokenizer::new(&src);
+    let t = Tokenizer::new(&src);
 
 
     let expected = vec![
@@ -56,10 +60,23 @@ fn tokenize_keywords_vs_identifiers() {
     assert_eq!(toks, expected);
 }
 
+#[test]
+fn tokenize_bool_literals() {
+    let src = "true || falsey".to_string();
+    let t = Tokenizer::new(&src);
+    let expected = vec![
+        (TokenType::Bool, "true".into()),
+        (TokenType::Operator, "||".into()),
+        (TokenType::Identifier, "falsey".into()),
+    ];
+    let toks = collect_types_and_values(t);
+    assert_eq!(toks, expected);
+}
+
 #[test]
 fn peek_does_not_advance() {
     let src = "x,y".to_string();
-    let mut t = Tokenizer::new(&src);
+    let t = Tokenizer::new(&src);
     let expected = vec![
         (TokenType::Identifier, "x".into()),
         (TokenType::Punctuation, ",".into()),
@@ -68,3 +85,424 @@ fn peek_does_not_advance() {
     let toks = collect_types_and_values(t);
     assert_eq!(toks, expected);
 }
+
+#[test]
+fn tokens_carry_spans() {
+    let src = "var x\n  y = 10".to_string();
+    let mut t = Tokenizer::new(&src);
+    let var = t.next_in_statement().unwrap();
+    assert_eq!((var.span.start, var.span.end, var.span.line, var.span.col), (0, 3, 1, 1));
+    let x = t.next_in_statement().unwrap();
+    assert_eq!((x.span.start, x.span.end, x.span.line, x.span.col), (4, 5, 1, 5));
+    t.eat_lines();
+    let y = t.next_in_statement().unwrap();
+    assert_eq!((y.span.start, y.span.end, y.span.line, y.span.col), (8, 9, 2, 3));
+    t.next_in_statement();
+    let ten = t.next_in_statement().unwrap();
+    assert_eq!((ten.span.start, ten.span.end, ten.span.line, ten.span.col), (12, 14, 2, 7));
+}
+
+#[test]
+fn columns_count_characters_on_long_lines() {
+    // long enough to cross many of the blocks columns are counted in
+    let src = format!("x\n{}end", "größe + ".repeat(100));
+    let end = Tokenizer::new(&src).last().unwrap();
+    assert_eq!(end.value, "end");
+    assert_eq!((end.span.line, end.span.col), (2, 801));
+}
+
+#[test]
+fn crlf_line_breaks_end_statements() {
+    let src = "a = 1 // one\r\nb\r\n";
+    let mut t = Tokenizer::new(src);
+    let values: Vec<_> = t.by_ref().map(|tok| tok.value).collect();
+    assert_eq!(values, vec!["a", "=", "1", "\n", "b", "\n"]);
+    assert!(t.diagnostics.is_empty());
+    let comment = &Tokenizer::tokenize_line("a // one\r", &LineState::default()).comments[0];
+    assert_eq!(comment.text, "// one");
+    assert_eq!(Tokenizer::line_states("\"a\r\nb\"\r\nc").len(), 3);
+}
+
+#[test]
+fn iterating_walks_past_terminators() {
+    let mut t = Tokenizer::new("a\nb");
+    assert_eq!(t.next_in_statement().unwrap().value, "a");
+    assert!(t.next_in_statement().is_none());
+    assert_eq!(t.by_ref().next().unwrap().value, "\n");
+    assert_eq!(t.next().unwrap().value, "b");
+}
+
+#[test]
+fn operators_use_maximal_munch() {
+    let src = "a=-b != !c && d%=e<=f".to_string();
+    let t = Tokenizer::new(&src);
+    let toks: Vec<String> = collect_types_and_values(t).into_iter().map(|(_, value)| value).collect();
+    assert_eq!(toks, vec!["a", "=", "-", "b", "!=", "!", "c", "&&", "d", "%=", "e", "<=", "f"]);
+}
+
+#[test]
+fn unknown_characters_are_reported_and_skipped() {
+    let src = "a @ b # c".to_string();
+    let mut t = Tokenizer::new(&src);
+    let mut values = Vec::new();
+    while let Some(tok) = t.next_in_statement() {
+        values.push(tok.value);
+    }
+    assert_eq!(values, vec!["a", "b", "c"]);
+    let diagnostics = t.diagnostics.into_vec();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].code, codes::UNKNOWN_CHARACTER);
+    assert_eq!(diagnostics[0].message, "unknown character `@`");
+    assert_eq!((diagnostics[1].span.start, diagnostics[1].span.end), (6, 7));
+}
+
+#[test]
+fn long_runs_of_unknown_characters_do_not_overflow_the_stack() {
+    let src = format!("a {} b", "@".repeat(20_000));
+    let mut t = Tokenizer::new(&src);
+    let values: Vec<_> = t.by_ref().map(|tok| tok.value).collect();
+    assert_eq!(values, vec!["a", "b"]);
+    assert_eq!(t.diagnostics.len(), 20_000);
+}
+
+#[test]
+fn numbers_are_ints_or_floats() {
+    let src = "7 3.25 1e9 2.5E-3 4.x".to_string();
+    let t = Tokenizer::new(&src);
+    let toks = collect_types_and_values(t);
+    assert_eq!(toks[..4], vec![
+        (TokenType::Number, "7".into()),
+        (TokenType::Float, "3.25".into()),
+        (TokenType::Float, "1e9".into()),
+        (TokenType::Float, "2.5E-3".into()),
+    ]);
+    assert_eq!(toks[4], (TokenType::Number, "4".into()));
+}
+
+#[test]
+fn malformed_numbers_are_reported_whole() {
+    let src = "1.2.3 + 12abc + 1e".to_string();
+    let mut t = Tokenizer::new(&src);
+    let mut values = Vec::new();
+    while let Some(tok) = t.next_in_statement() {
+        values.push(tok.value);
+    }
+    assert_eq!(values, vec!["1.2.3", "+", "12abc", "+", "1e"]);
+    let diagnostics = t.diagnostics.into_vec();
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, vec![
+        "malformed number literal `1.2.3`",
+        "malformed number literal `12abc`",
+        "malformed number literal `1e`",
+    ]);
+    assert!(diagnostics.iter().all(|d| d.code == codes::MALFORMED_NUMBER));
+}
+
+#[test]
+fn number_literals_carry_their_value() {
+    let src = "0xFF_FF 0b1010 0o17 1_000_000 2.5e2 9223372036854775807".to_string();
+    let mut t = Tokenizer::new(&src);
+    let mut literals = Vec::new();
+    while let Some(tok) = t.next_in_statement() {
+        literals.push(tok.literal);
+    }
+    assert_eq!(literals, vec![
+        Some(Literal::Int(0xFFFF)),
+        Some(Literal::Int(10)),
+        Some(Literal::Int(15)),
+        Some(Literal::Int(1_000_000)),
+        Some(Literal::Float(250.0)),
+        Some(Literal::Int(i64::MAX)),
+    ]);
+    assert!(t.diagnostics.is_empty());
+}
+
+#[test]
+fn bad_prefixed_and_oversized_literals_are_reported() {
+    let src = "0x 0b102 9223372036854775808 0xFFFFFFFFFFFFFFFFF 1e999 1__000 1_ 0x_FF 1_.5".to_string();
+    let mut t = Tokenizer::new(&src);
+    let mut values = Vec::new();
+    while let Some(tok) = t.next_in_statement() {
+        assert_eq!(tok.literal, None);
+        values.push(tok.value);
+    }
+    assert_eq!(values, vec!["0x", "0b102", "9223372036854775808", "0xFFFFFFFFFFFFFFFFF", "1e999", "1__000", "1_", "0x_FF", "1_.5"]);
+    let codes: Vec<_> = t.diagnostics.into_vec().iter().map(|d| d.code).collect();
+    assert_eq!(codes, vec![
+        codes::MALFORMED_NUMBER,
+        codes::MALFORMED_NUMBER,
+        codes::NUMBER_OVERFLOW,
+        codes::NUMBER_OVERFLOW,
+        codes::NUMBER_OVERFLOW,
+        codes::MALFORMED_NUMBER,
+        codes::MALFORMED_NUMBER,
+        codes::MALFORMED_NUMBER,
+        codes::MALFORMED_NUMBER,
+    ]);
+}
+
+#[test]
+fn string_escapes_are_decoded() {
+    let src = r#""a\tb\n\"q\" \\ \u{48}\u{1F600}""#.to_string();
+    let mut t = Tokenizer::new(&src);
+    let token = t.next_in_statement().unwrap();
+    assert_eq!(token.value, "a\tb\n\"q\" \\ H\u{1F600}");
+    assert_eq!((token.span.start, token.span.end), (0, src.len()));
+    assert!(t.diagnostics.is_empty());
+}
+
+#[test]
+fn raw_strings_keep_backslashes_and_quotes() {
+    let src = r####"r"C:\dir" r#"say "hi"\n"# x"####.to_string();
+    let t = Tokenizer::new(&src);
+    let toks = collect_types_and_values(t);
+    assert_eq!(toks, vec![
+        (TokenType::String, r"C:\dir".into()),
+        (TokenType::String, r#"say "hi"\n"#.into()),
+        (TokenType::Identifier, "x".into()),
+    ]);
+}
+
+#[test]
+fn bad_escapes_are_reported() {
+    let src = r#""\q \u{110000} \u41""#.to_string();
+    let mut t = Tokenizer::new(&src);
+    assert_eq!(t.next_in_statement().unwrap().value, "  41");
+    let diagnostics = t.diagnostics.into_vec();
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, vec![
+        "unknown escape sequence `\\q`",
+        "invalid unicode escape `\\u{110000}`",
+        "invalid unicode escape `\\u`",
+    ]);
+    assert!(diagnostics.iter().all(|d| d.code == codes::INVALID_ESCAPE));
+}
+
+#[test]
+fn unterminated_string_points_at_opening_quote() {
+    let src = "x = \"never\nclosed".to_string();
+    let mut t = Tokenizer::new(&src);
+    while t.next_in_statement().is_some() {}
+    let diagnostics = t.diagnostics.into_vec();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, codes::UNTERMINATED_STRING);
+    assert_eq!((diagnostics[0].span.start, diagnostics[0].span.end), (4, 5));
+    assert_eq!((diagnostics[0].span.line, diagnostics[0].span.col), (1, 5));
+}
+
+#[test]
+fn interpolated_strings_become_templates() {
+    let src = r#""hi {name}, {{ok}} {greet("x}")}!""#.to_string();
+    let mut t = Tokenizer::new(&src);
+    let token = t.next_in_statement().unwrap();
+    assert_eq!(token.type_, TokenType::Template);
+    let Some(Literal::Template(parts)) = token.literal else {
+        panic!("expected template parts");
+    };
+    let parts: Vec<_> = parts
+        .iter()
+        .map(|part| match part {
+            TemplatePart::Text(text) => text.clone(),
+            TemplatePart::Code(span) => format!("<{}>", &src[span.start..span.end]),
+        })
+        .collect();
+    assert_eq!(parts, vec!["hi ", "<name>", ", {ok} ", "<greet(\"x}\")>", "!"]);
+    assert!(t.next_in_statement().is_none());
+}
+
+#[test]
+fn unclosed_interpolation_is_reported() {
+    let src = "\"a {b\"\nc".to_string();
+    let mut t = Tokenizer::new(&src);
+    let token = t.next_in_statement().unwrap();
+    assert_eq!(token.type_, TokenType::String);
+    let diagnostics = t.diagnostics.into_vec();
+    assert_eq!(diagnostics[0].code, codes::UNTERMINATED_INTERPOLATION);
+    assert_eq!((diagnostics[0].span.start, diagnostics[0].span.end), (3, 4));
+}
+
+#[test]
+fn comments_are_trivia_on_the_next_token() {
+    let src = "a // trailing\n/* one /* nested */ still one */ b /**/ c".to_string();
+    let mut t = Tokenizer::new(&src);
+    let a = t.next_in_statement().unwrap();
+    assert!(a.trivia.is_empty());
+    assert!(t.next_in_statement().is_none());
+    t.eat_lines();
+    let b = t.next_in_statement().unwrap();
+    assert_eq!(b.value, "b");
+    let comments: Vec<_> = b.trivia.iter().map(|trivia| (trivia.kind, trivia.text.as_str())).collect();
+    assert_eq!(comments, vec![
+        (TriviaKind::LineComment, "// trailing"),
+        (TriviaKind::BlockComment, "/* one /* nested */ still one */"),
+    ]);
+    assert_eq!(b.trivia[1].span.line, 2);
+    let c = t.next_in_statement().unwrap();
+    assert_eq!(c.trivia[0].text, "/**/");
+    assert!(t.diagnostics.is_empty());
+}
+
+#[test]
+fn unterminated_block_comment_is_reported() {
+    let src = "a /* /* */ b".to_string();
+    let mut t = Tokenizer::new(&src);
+    assert_eq!(t.next_in_statement().unwrap().value, "a");
+    assert!(t.next_in_statement().is_none());
+    let diagnostics = t.diagnostics.into_vec();
+    assert_eq!(diagnostics[0].code, codes::UNTERMINATED_COMMENT);
+    assert_eq!((diagnostics[0].span.start, diagnostics[0].span.end), (2, 4));
+}
+
+#[test]
+fn doc_comments_are_tokens() {
+    let src = "/// Adds.\n//// not a doc\nx".to_string();
+    let mut t = Tokenizer::new(&src);
+    let doc = t.next_in_statement().unwrap();
+    assert_eq!((doc.type_, doc.value.as_str()), (TokenType::DocComment, "Adds."));
+    // a line break after a doc comment does not end a statement
+    let x = t.next_in_statement().unwrap();
+    assert_eq!(x.value, "x");
+    assert_eq!(x.trivia[0].text, "//// not a doc");
+}
+
+#[test]
+fn unicode_identifiers_and_strings() {
+    let src = "größe = \"héllo ✓\" + 名前2 + _x".to_string();
+    let mut t = Tokenizer::new(&src);
+    let mut tokens = Vec::new();
+    while let Some(tok) = t.next_in_statement() {
+        tokens.push(tok);
+    }
+    let values: Vec<_> = tokens.iter().map(|tok| (tok.type_.clone(), tok.value.as_str())).collect();
+    assert_eq!(values, vec![
+        (TokenType::Identifier, "größe"),
+        (TokenType::Operator, "="),
+        (TokenType::String, "héllo ✓"),
+        (TokenType::Operator, "+"),
+        (TokenType::Identifier, "名前2"),
+        (TokenType::Operator, "+"),
+        (TokenType::Identifier, "_x"),
+    ]);
+    // spans are byte offsets, columns count characters
+    let name = &tokens[4];
+    assert_eq!(&src[name.span.start..name.span.end], "名前2");
+    assert_eq!(name.span.col, 21);
+    assert!(t.diagnostics.is_empty());
+}
+
+#[test]
+fn non_ascii_unknown_character_is_skipped_whole() {
+    let src = "a → b".to_string();
+    let mut t = Tokenizer::new(&src);
+    let mut values = Vec::new();
+    while let Some(tok) = t.next_in_statement() {
+        values.push(tok.value);
+    }
+    assert_eq!(values, vec!["a", "b"]);
+    let diagnostics = t.diagnostics.into_vec();
+    assert_eq!(diagnostics[0].message, "unknown character `→`");
+    assert_eq!((diagnostics[0].span.start, diagnostics[0].span.end), (2, 5));
+}
+
+#[test]
+fn trace_hook_sees_tokens_unknown_characters_and_backtracking() {
+    let src = "a $ (b".to_string();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut t = Tokenizer::new(&src);
+    let sink = Rc::clone(&events);
+    t.set_trace(move |event| {
+        sink.borrow_mut().push(match event {
+            TraceEvent::Token(token) => format!("token {}", token.value),
+            TraceEvent::UnknownCharacter { character, .. } => format!("unknown {}", character),
+            TraceEvent::Backtrack { from, to } => format!("backtrack {}->{}", from, to),
+            TraceEvent::ListItem { index, .. } => format!("item {}", index),
+        })
+    });
+    t.next_in_statement();
+    // peeking is not traced
+    t.peek();
+    assert!(!t.optionally_expect_punctuation(')'));
+    // backtracking reuses the lexed tokens, so `$` is only reported once
+    t.next_in_statement();
+    assert_eq!(*events.borrow(), vec!["token a", "unknown $", "token (", "backtrack 2->1", "token ("]);
+}
+
+#[test]
+fn identifiers_may_contain_digits() {
+    let src = "point2d x1_y2".to_string();
+    let toks = collect_types_and_values(Tokenizer::new(&src));
+    assert_eq!(toks, vec![(TokenType::Identifier, "point2d".into()), (TokenType::Identifier, "x1_y2".into())]);
+}
+
+fn values(line: &Line) -> Vec<&str> {
+    line.tokens.iter().map(|tok| tok.value.as_str()).collect()
+}
+
+#[test]
+fn line_states_carry_block_comments_and_strings() {
+    let src = "a /* one\ntwo /* nested\n*/ still */ b\nx = \"abc\ndef\" + r#\"raw\n\"q\"# y";
+    let states = Tokenizer::line_states(src);
+    let comment = OpenConstruct::BlockComment;
+    let string = OpenConstruct::String { raw_hashes: None };
+    assert_eq!(states, vec![
+        LineState::default(),
+        LineState::new(vec![comment]),
+        LineState::new(vec![comment, comment]),
+        LineState::default(),
+        LineState::new(vec![string]),
+        LineState::new(vec![OpenConstruct::String { raw_hashes: Some(1) }]),
+    ]);
+
+    let line = Tokenizer::tokenize_line("*/ still */ b", &states[2]);
+    assert_eq!(values(&line), vec!["b"]);
+    assert_eq!(line.comments[0].text, "*/ still */");
+    assert!(line.end_state.is_code());
+
+    let line = Tokenizer::tokenize_line("def\" + y // done", &states[4]);
+    assert_eq!(values(&line), vec!["def", "+", "y"]);
+    assert_eq!(line.tokens[0].type_, TokenType::String);
+    assert_eq!(line.comments[0].text, "// done");
+
+    let line = Tokenizer::tokenize_line("\"q\"# y", &states[5]);
+    assert_eq!(values(&line), vec!["\"q", "y"]);
+}
+
+#[test]
+fn line_states_track_strings_nested_in_interpolations() {
+    let first = Tokenizer::tokenize_line("s = \"a {f(\"b", &LineState::default());
+    let string = OpenConstruct::String { raw_hashes: None };
+    assert_eq!(
+        first.end_state,
+        LineState::new(vec![string, OpenConstruct::Interpolation { braces: 0 }, string])
+    );
+    let second = Tokenizer::tokenize_line("c\") + 1} d\" + t", &first.end_state);
+    assert_eq!(values(&second), vec!["c", ")", "+", "1", "}", " d", "+", "t"]);
+    assert!(second.end_state.is_code());
+}
+
+#[test]
+fn token_stream_peeks_ahead_and_iterates_across_lines() {
+    let src = "f(x)\ny".to_string();
+    let mut t = Tokenizer::new(&src);
+    assert_eq!(t.peek_nth(2).map(|tok| tok.value.as_str()), Some("x"));
+    // lookahead stops at the line break, like `next_in_statement`
+    assert!(t.peek_nth(4).is_none());
+    assert_eq!(t.next_in_statement().unwrap().value, "f");
+
+    let all: Vec<_> = Tokenizer::new(&src).map(|tok| (tok.type_, tok.value)).collect();
+    assert_eq!(all, vec![
+        (TokenType::Identifier, "f".into()),
+        (TokenType::Punctuation, "(".into()),
+        (TokenType::Identifier, "x".into()),
+        (TokenType::Punctuation, ")".into()),
+        (TokenType::Terminator, "\n".into()),
+        (TokenType::Identifier, "y".into()),
+    ]);
+}
+
+#[test]
+fn line_breaks_end_statements_only_after_complete_ones() {
+    let src = "a +\nb\n\n(c);d\nreturn\nf(\nx)".to_string();
+    let all: Vec<_> = Tokenizer::new(&src).map(|tok| tok.value).collect();
+    assert_eq!(all, vec!["a", "+", "b", "\n", "(", "c", ")", ";", "d", "\n", "return", "\n", "f", "(", "x", ")"]);
+}